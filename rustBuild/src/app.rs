@@ -2,6 +2,12 @@ mod app_panels;
 mod app_widgets;
 mod cube_painter;
 mod data;
+mod derived;
+mod diff;
+mod import;
+mod jobs;
+mod loader;
+mod search;
 mod tree;
 mod ui_helper;
 
@@ -11,16 +17,34 @@ use cgmath::Euler;
 use cube_painter::*;
 use data::*;
 use egui::mutex::Mutex;
+use jobs::*;
+use loader::*;
 use std::sync::Arc;
 use tree::*;
+use ui_helper::NodeDetails;
 
 use self::app_widgets::WhatGraph;
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 struct OptionsMenu {
     inspector_options: MeshOptions,
+    inspector_selection: InspectorSelection,
+    ///an earlier slice the inspector is colored against, picked with a shift click
+    inspector_compare: Option<InspectorSelection>,
     inspector_height: usize,
+    ///the last node clicked in the inspector, shown in the bottom panel
+    pinned: Option<NodeDetails>,
+    ///what was pinned before the last click, put back if that click starts a double click
+    unpinned: Option<NodeDetails>,
+    ///the call path the inspector is zoomed into, empty to show the whole slice
+    zoom: Vec<String>,
+    ///the 3d graphs are zoomed into the same call path as the inspector
+    zoom_graphs: bool,
+    ///function names are drawn on the inspector's bars that are wide enough
+    bar_labels: bool,
     modify_options: ModifyOptions,
+    derived_options: DerivedOptions,
+    search_options: SearchOptions,
     visual_options: egui::Visuals,
 }
 struct InteractiveViewOptions {
@@ -30,6 +54,8 @@ struct InteractiveViewOptions {
 }
 struct Graph3D {
     painter: Arc<Mutex<CubePainter>>,
+    ///the mesh being built for the current options, the painter is replaced when it is done
+    job: Option<MeshJob>,
     mesh_options: MeshOptions,
     view_options: InteractiveViewOptions,
 }
@@ -40,6 +66,23 @@ struct ModifyOptions {
     new_popup: bool,
     window_position: egui::Pos2,
 }
+///expressions are kept as text so they can be parsed again against each new file's metrics
+#[derive(Default)]
+struct DerivedOptions {
+    text: String,
+    expressions: Vec<String>,
+    error: Option<String>,
+}
+///the search box, matching functions are highlighted in every graph
+#[derive(Default)]
+struct SearchOptions {
+    text: String,
+    regex: bool,
+    pattern: Option<search::NamePattern>,
+    error: Option<String>,
+    ///the share of the inspector's metric spent in matching functions
+    matched_share: f64,
+}
 #[derive(Debug, Clone, PartialEq)]
 enum ClearOptions {
     NeedsFirstClear,
@@ -69,11 +112,15 @@ pub struct App {
     graph_right: Graph3D,
     rect_painter: Arc<Mutex<CubePainter>>,
     inspector_mesh: Mesh,
-    master_graph: MasterTree,
+    inspector_paths: PathIndex,
+    inspector_job: Option<MeshJob>,
+    ///shared with the mesh jobs, changes go through `Arc::make_mut`
+    master_graph: Arc<MasterTree>,
     inspector_graph: Tree,
     data_info: TreeInfo,
     ui_state: OptionsMenu,
     clear_options: ClearOptions,
+    file_loader: FileLoader,
 }
 impl App {
     /// Called once before the first frame.
@@ -82,19 +129,28 @@ impl App {
             .gl
             .as_ref()
             .expect("You need to run eframe with the glow backend");
-        let info = get_input_info();
+        //nothing is loaded until a trace file is opened or dropped in
+        let (master, info) = grow_master_tree(&[]);
         let l_options = MeshOptions::new_3d(&info);
         let r_options = MeshOptions::new_3d(&info);
-        let master = grow_master_tree();
-        let mesh = get_mesh_from_tree(&master, &l_options);
+        let mesh = get_mesh_from_tree(&master, &[], &l_options, None, &JobProgress::default())
+            .unwrap_or_default();
 
         let ins_options = MeshOptions::new_2d(&info);
-        let tree = build_time_tree(&master, info.start..info.end);
-        let insm = get_rects_from_tree(&tree, &ins_options);
+        let selection = InspectorSelection::Time(ins_options.time_range.clone());
+        let tree = selection.build_tree(&master, &ins_options);
+        let (insm, paths) = get_rects_from_tree(&tree, &ins_options);
 
         let ui = OptionsMenu {
             inspector_height: 12,
+            pinned: None,
+            unpinned: None,
+            zoom: vec![],
+            zoom_graphs: false,
+            bar_labels: true,
             inspector_options: ins_options,
+            inspector_selection: selection,
+            inspector_compare: None,
             modify_options: ModifyOptions {
                 highlighting_node: false,
                 open: false,
@@ -103,11 +159,12 @@ impl App {
                     values: trace_zero(),
                     children: vec![],
                     color: None,
-                    offsets: trace_zero(),
                 },
                 new_popup: false,
                 window_position: egui::pos2(0.0, 0.0),
             },
+            derived_options: Default::default(),
+            search_options: Default::default(),
             visual_options: egui::Visuals::dark(),
         };
 
@@ -116,19 +173,24 @@ impl App {
             ui_state: ui,
             graph_left: Graph3D {
                 painter: Arc::new(Mutex::new(CubePainter::new(gl, &mesh.verts, &mesh.colors))),
+                job: None,
                 mesh_options: l_options,
                 view_options: Default::default(),
             },
             graph_right: Graph3D {
                 painter: Arc::new(Mutex::new(CubePainter::new(gl, &mesh.verts, &mesh.colors))),
+                job: None,
                 mesh_options: r_options,
                 view_options: Default::default(),
             },
             rect_painter: Arc::new(Mutex::new(CubePainter::new(gl, &insm.verts, &insm.colors))),
             inspector_mesh: insm,
+            inspector_paths: paths,
+            inspector_job: None,
             data_info: info,
-            master_graph: master,
+            master_graph: Arc::new(master),
             clear_options: ClearOptions::NeedsFirstClear,
+            file_loader: Default::default(),
         }
     }
 }
@@ -141,7 +203,9 @@ impl eframe::App for App {
             self.clear_options = ClearOptions::HasBeenCleared;
         }
         // self.option_menu(ctx, frame);
+        self.receive_meshes(ctx, frame);
         self.set_web_options(ctx);
+        self.receive_files(ctx, frame);
         if self.ui_state.modify_options.open {
             self.modify_trace(ctx, frame);
         }
@@ -157,8 +221,15 @@ impl eframe::App for App {
                     ui.vertical_centered(|ui| {
                         ui.heading("EasyView Inspector");
                     });
+                    ui.horizontal(|ui| {
+                        self.open_file_button(ui);
+                    });
+                    ui.horizontal(|ui| {
+                        self.baseline_buttons(ui, frame);
+                    });
                 });
             });
+        self.error_panel(ctx);
         egui::SidePanel::left("left")
             .resizable(true)
             .show(ctx, |ui| {
@@ -171,7 +242,9 @@ impl eframe::App for App {
             });
         egui::TopBottomPanel::bottom("bottom")
             .resizable(true)
-            .show(ctx, |_ui| {});
+            .show(ctx, |ui| {
+                self.bottom_panel(ui, frame);
+            });
         egui::CentralPanel::default().show(ctx, |ui| {
             self.central_panel(ui, frame);
         });
@@ -1,3 +1,4 @@
+use crate::app::app_widgets::job_progress;
 use crate::app::*;
 
 impl App {
@@ -5,21 +6,49 @@ impl App {
     pub fn central_panel(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
         ui.collapsing("Options", |ui| {
             // ui.horizontal(|ui| {
-            if self.ui_state.inspector_options.across_metric == AcrossMetric::Time {
-                self.slider_start_time(frame, ui, WhatGraph::Inspector);
-                self.slider_end_time(frame, ui, WhatGraph::Inspector);
-            } else {
-                ui.label(
-                    "Inspecting Thread: ".to_owned()
-                        + &(self.ui_state.inspector_options.num_threads + 1).to_string(),
-                );
+            match self.ui_state.inspector_selection {
+                InspectorSelection::Time(_) => {
+                    self.slider_start_time(frame, ui, WhatGraph::Inspector);
+                    self.slider_end_time(frame, ui, WhatGraph::Inspector);
+                }
+                InspectorSelection::Thread(thread) => {
+                    ui.label(
+                        "Inspecting Thread: ".to_owned()
+                            + &self.data_info.threads.thread_name(thread),
+                    );
+                }
+                InspectorSelection::Process(process) => {
+                    ui.label(
+                        "Inspecting Process: ".to_owned()
+                            + &self.data_info.threads.process_name(process),
+                    );
+                }
+            }
+            if let Some(compare) = &self.ui_state.inspector_compare {
+                let mut stop = false;
+                ui.horizontal(|ui| {
+                    ui.label("Compared Against: ".to_owned() + &compare.describe(&self.data_info));
+                    stop = ui.button("Stop Comparing").clicked();
+                });
+                if stop {
+                    self.ui_state.inspector_compare = None;
+                    self.regen_inspector(frame);
+                }
             }
             ui.horizontal(|ui| {
                 self.color_dropdown(frame, ui);
                 self.salt_drag_value(frame, ui);
             });
+            ui.checkbox(&mut self.ui_state.bar_labels, "Labels");
+            self.derived_metric_editor(ui);
             // });
         });
+        if self.file_loader.file_name.is_none() {
+            ui.label("Open or drop a trace file to begin");
+        }
+        self.search_box(ui, frame);
+        self.breadcrumbs(ui, frame);
+        job_progress(ui, &self.inspector_job);
         egui::Frame::canvas(ui.style()).show(ui, |ui| {
             self.inspector_veiw(ui, frame);
         });
@@ -34,16 +63,19 @@ impl App {
                 self.slider_start_time(frame, ui, WhatGraph::LeftGraph);
                 self.slider_end_time(frame, ui, WhatGraph::LeftGraph);
                 self.slider_num_graphs(frame, ui, WhatGraph::LeftGraph);
+                self.attribution_dropdown(frame, ui, WhatGraph::LeftGraph);
             }
             self.division_checkbox(frame, ui, WhatGraph::LeftGraph);
         });
 
         // });
+        job_progress(ui, &self.graph_left.job);
         egui::Frame::canvas(ui.style()).show(ui, |ui| {
-            self.graph_left.interactive_view(ui);
+            self.graph_left.interactive_view(ui, &self.data_info);
         });
         egui::Frame::canvas(ui.style()).show(ui, |ui| {
-            self.graph_left.topdown_view(ui, frame, &mut self.ui_state);
+            self.graph_left
+                .topdown_view(ui, frame, &mut self.ui_state, &self.data_info);
         });
     }
     pub fn right_panel(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
@@ -55,14 +87,75 @@ impl App {
                 self.slider_start_time(frame, ui, WhatGraph::RightGraph);
                 self.slider_end_time(frame, ui, WhatGraph::RightGraph);
                 self.slider_num_graphs(frame, ui, WhatGraph::RightGraph);
+                self.attribution_dropdown(frame, ui, WhatGraph::RightGraph);
             }
             self.division_checkbox(frame, ui, WhatGraph::RightGraph);
         });
+        job_progress(ui, &self.graph_right.job);
         egui::Frame::canvas(ui.style()).show(ui, |ui| {
-            self.graph_right.interactive_view(ui);
+            self.graph_right.interactive_view(ui, &self.data_info);
         });
         egui::Frame::canvas(ui.style()).show(ui, |ui| {
-            self.graph_right.topdown_view(ui, frame, &mut self.ui_state);
+            self.graph_right
+                .topdown_view(ui, frame, &mut self.ui_state, &self.data_info);
+        });
+    }
+    ///the bottom panel pins the details and stack of the node last clicked in the inspector
+    pub fn bottom_panel(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
+        let Some(details) = &self.ui_state.pinned else {
+            return;
+        };
+        let stack = details.stack.join("\n");
+        let mut close = false;
+        let mut zoom = false;
+        ui.horizontal(|ui| {
+            ui.label("Pinned: ".to_owned() + &details.name);
+            zoom = ui.button("Zoom In").clicked();
+            if ui.button("Copy Name").clicked() {
+                ui.output().copied_text = details.name.clone();
+            }
+            if ui.button("Copy Stack").clicked() {
+                ui.output().copied_text = stack.clone();
+            }
+            //rows of several nodes can be pasted one after another under a single header
+            if ui.button("Copy Row").clicked() {
+                ui.output().copied_text = details.tsv_row();
+            }
+            if ui.button("Copy Header").clicked() {
+                ui.output().copied_text = details.tsv_header();
+            }
+            close = ui.button("Close").clicked();
+        });
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal_top(|ui| {
+                egui::Grid::new("pinned_details")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (name, amount, percent) in &details.measures {
+                            ui.label(name);
+                            ui.label(amount.to_string());
+                            ui.label(format!("{:.2}% of this slice", percent));
+                            ui.end_row();
+                        }
+                        ui.label("Children");
+                        ui.label(details.children.to_string());
+                        ui.label(format!("{} below", details.descendants));
+                        ui.end_row();
+                    });
+                //a text edit over a str can be selected and copied but not changed
+                ui.add(
+                    egui::TextEdit::multiline(&mut stack.as_str())
+                        .code_editor()
+                        .desired_width(f32::INFINITY),
+                );
+            });
         });
+        if zoom {
+            let path = details.stack.clone();
+            self.set_zoom(path, frame);
+        }
+        if close {
+            self.ui_state.pinned = None;
+        }
     }
 }
@@ -3,6 +3,9 @@ use std::sync::Arc;
 use super::{App, ClearOptions, CubePainter};
 use super::{Graph3D, OptionsMenu};
 use crate::app::data::*;
+use crate::app::derived::*;
+use crate::app::jobs::*;
+use crate::app::search::*;
 use crate::app::tree::*;
 use crate::app::ui_helper::*;
 
@@ -27,8 +30,10 @@ pub enum WhatGraph {
     RightGraph,
     Inspector,
 }
+///most axis labels drawn along the 3d graph, so a hundred threads stay readable
+const MAX_AXIS_LABELS: usize = 16;
 impl Graph3D {
-    pub fn interactive_view(&mut self, ui: &mut egui::Ui) {
+    pub fn interactive_view(&mut self, ui: &mut egui::Ui, info: &TreeInfo) {
         let (rect, response) = ui.allocate_exact_size(
             egui::Vec2::splat(ui.available_size().y.min(ui.available_size().x)),
             egui::Sense::union(egui::Sense::drag(), egui::Sense::hover()),
@@ -77,6 +82,37 @@ impl Graph3D {
             self.view_options = Default::default();
         }
         ui.painter().add(callback);
+        self.axis_labels(ui, rect, m, info);
+    }
+    ///names each slice of the graph beside the base of its bars
+    fn axis_labels(
+        &self,
+        ui: &mut egui::Ui,
+        rect: egui::Rect,
+        matrix: cgmath::Matrix4<f32>,
+        info: &TreeInfo,
+    ) {
+        let num_divisions = self.mesh_options.num_divisions();
+        let step = (num_divisions + MAX_AXIS_LABELS - 1) / MAX_AXIS_LABELS;
+        //the base of the bars, see verts_from_overlaps
+        let base = LENGTH_OFFSET - 0.9 * LENGTH_MOD;
+        let depth = 1.0 / -2.0_f32.powf(0.2) + 1.0;
+        for n in (0..num_divisions).step_by(step.max(1)) {
+            let middle = (n as f32 + 0.5) / num_divisions as f32 * BREDTH_MOD - BREDTH_OFFSET;
+            let Some(pos) = world_to_screen(rect, [base, depth, middle], matrix) else {
+                continue;
+            };
+            if !rect.contains(pos) {
+                continue;
+            }
+            ui.painter().text(
+                pos,
+                egui::Align2::RIGHT_CENTER,
+                self.mesh_options.division_name(n, info),
+                egui::FontId::proportional(11.0),
+                ui.visuals().text_color(),
+            );
+        }
     }
 
     pub fn topdown_view(
@@ -84,6 +120,7 @@ impl Graph3D {
         ui: &mut egui::Ui,
         _frame: &mut eframe::Frame,
         inspector: &mut OptionsMenu,
+        info: &TreeInfo,
     ) {
         // Clone locals so we can move them into the paint callback:
         //TODO maybe at some point have the graphs rotate based on what metrics are tracked for visual clarity
@@ -117,39 +154,56 @@ impl Graph3D {
             })),
         };
         if response.hover_pos().is_some() {
-            let num_graphs = match self.mesh_options.across_metric {
-                AcrossMetric::Time => self.mesh_options.num_graphs,
-                AcrossMetric::Thread => self.mesh_options.num_threads,
-            };
+            let num_graphs = self.mesh_options.num_divisions();
             let text = match self.mesh_options.across_metric {
                 AcrossMetric::Time => "Select Time Slice: ",
                 AcrossMetric::Thread => "Select Thread: ",
+                AcrossMetric::Process => "Select Process: ",
             };
             let n = overview_lookup(num_graphs, rect, response.hover_pos().unwrap());
-            if n.is_some() {
+            if let Some(n) = n {
                 if response.clicked() {
-                    let m_opt = &self.mesh_options;
-                    let graph_size =
-                        (m_opt.time_range.end - m_opt.time_range.start) as f64 / num_graphs as f64;
-                    inspector.inspector_options.time_range = (n.unwrap() as f64 * graph_size
-                        + m_opt.time_range.start as f64)
-                        as u64
-                        ..((n.unwrap() as f64 + 1.0) * graph_size + m_opt.time_range.start as f64)
-                            as u64;
+                    let selection = InspectorSelection::of_division(&self.mesh_options, n);
+                    //a shift click keeps the inspected slice and compares it against this one
+                    if ui.input().modifiers.shift {
+                        inspector.inspector_compare = Some(selection);
+                    } else {
+                        inspector.inspector_selection = selection;
+                    }
                     inspector.inspector_options.data_metric = self.mesh_options.data_metric.clone();
-                    inspector.inspector_options.across_metric =
-                        self.mesh_options.across_metric.clone();
-                    inspector.inspector_options.num_threads = n.unwrap() - 1;
+                    inspector.inspector_options.attribution = self.mesh_options.attribution;
                     inspector.inspector_options.has_changed = true;
                 }
-                response.on_hover_text_at_pointer(text.to_string() + &(n.unwrap()).to_string());
+                let mut text = text.to_string() + &self.mesh_options.division_name(n, info);
+                if self.mesh_options.across_metric == AcrossMetric::Thread {
+                    if let Some(thread) = info.threads.entries.get(n) {
+                        for (key, value) in &thread.metadata {
+                            text += &format!("\n {}:    {}", key, value);
+                        }
+                    }
+                }
+                text += "\nShift click to compare the inspector against it";
+                response.on_hover_text_at_pointer(text);
             }
         }
         ui.painter().add(callback);
     }
 }
+///shows how far along a graph's mesh job is, nothing once it is done
+pub fn job_progress(ui: &mut egui::Ui, job: &Option<MeshJob>) {
+    let Some(job) = job else {
+        return;
+    };
+    ui.horizontal(|ui| {
+        match job.fraction() {
+            Some(fraction) => ui.add(egui::ProgressBar::new(fraction).show_percentage()),
+            None => ui.spinner(),
+        };
+        ui.label("Building graph");
+    });
+}
 impl App {
-    pub fn inspector_veiw(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+    pub fn inspector_veiw(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
         let min_size = f32::min(ui.available_size().y, ui.available_size().x);
         let (rect, response) = ui.allocate_exact_size(
             egui::Vec2::splat(min_size),
@@ -164,38 +218,38 @@ impl App {
         if response.hover_pos().is_some() {
             let n = inspector_lookup(
                 &self.inspector_mesh.verts,
+                &self.inspector_paths,
                 &self.inspector_graph,
                 rect,
                 response.hover_pos().unwrap(),
                 m,
             );
-            if n.is_some() {
-                let u_n = n.unwrap().1;
-
-                let percent_dur =
-                    u_n.values.dur as f32 / self.inspector_graph.root.values.dur as f32 / 0.01;
-                let percent_val =
-                    u_n.values.value as f32 / self.inspector_graph.root.values.value as f32 / 0.01;
-                if response.clicked() {
+            if let Some((index, u_n)) = n {
+                //the first click of a double click has already opened the popup and pinned
+                //the node, zooming takes both back
+                if response.double_clicked() {
+                    self.ui_state.modify_options.open = false;
+                    self.ui_state.pinned = self.ui_state.unpinned.take();
+                    let stack = call_stack(&self.inspector_graph, &self.inspector_paths, index);
+                    let path = self.zoom_path(stack);
+                    self.set_zoom(path, frame);
+                } else if response.clicked() {
                     self.ui_state.modify_options.open = true;
                     self.ui_state.modify_options.new_popup = true;
                     self.ui_state.modify_options.node = u_n.clone();
                     self.ui_state.modify_options.window_position = response.hover_pos().unwrap();
+                    let stack = call_stack(&self.inspector_graph, &self.inspector_paths, index);
+                    let stack = self.zoom_path(stack);
+                    self.ui_state.unpinned = self.ui_state.pinned.replace(NodeDetails::new(
+                        u_n,
+                        &self.inspector_graph,
+                        &self.data_info,
+                        stack,
+                    ));
                 } else {
-                    response.on_hover_text_at_pointer(
-                        " Name:        ".to_owned()
-                            + &u_n.name.clone()
-                            + "\n Duration:     ".clone()
-                            + &u_n.values.dur.to_string()
-                            + "    "
-                            + &percent_dur.to_string()
-                            + "% of this slice"
-                            + "\n Value:        ".clone()
-                            + &u_n.values.value.to_string()
-                            + "    "
-                            + &percent_val.to_string()
-                            + "% of this slice",
-                    );
+                    let details =
+                        NodeDetails::new(u_n, &self.inspector_graph, &self.data_info, vec![]);
+                    response.on_hover_text_at_pointer(details.hover_text());
                 }
             }
         }
@@ -208,8 +262,109 @@ impl App {
             })),
         };
         ui.painter().add(callback);
+        if self.ui_state.bar_labels {
+            self.bar_labels(ui, rect, m);
+        }
+    }
+    ///names drawn over the inspector's bars, cut short to fit and left off bars too small
+    fn bar_labels(&self, ui: &mut egui::Ui, rect: egui::Rect, m: cgmath::Matrix4<f32>) {
+        const PADDING: f32 = 2.0;
+        let font = egui::TextStyle::Small.resolve(ui.style());
+        let nodes = nodes_breadth_first(&self.inspector_graph);
+        let verts = &self.inspector_mesh.verts;
+        let mut labels = vec![];
+        //the fonts stay locked while measuring, so the labels are painted after
+        let fonts = ui.fonts();
+        for (k, n) in self.inspector_paths.rects.iter().enumerate() {
+            let i = k * VERTS_IN_RECT;
+            let (Some(node), Some(upper)) = (nodes.get(*n), verts.get(i + 5)) else {
+                continue;
+            };
+            let bar = rect_to_screen(rect, verts[i], *upper, m).intersect(rect);
+            if bar.height() < font.size || bar.width() < font.size * 2.0 {
+                continue;
+            }
+            let width = bar.width() - 2.0 * PADDING;
+            let Some(text) = fit_text(&node.name, width, |c| fonts.glyph_width(&font, c)) else {
+                continue;
+            };
+            //dark text on light bars, light text on dark ones
+            let color = self.inspector_mesh.colors[i];
+            let luminance = 0.299 * color[0] + 0.587 * color[1] + 0.114 * color[2];
+            let text_color = if luminance > 0.5 {
+                egui::Color32::BLACK
+            } else {
+                egui::Color32::WHITE
+            };
+            labels.push((
+                bar.left_center() + egui::vec2(PADDING, 0.0),
+                text,
+                text_color,
+            ));
+        }
+        drop(fonts);
+        let painter = ui.painter_at(rect);
+        for (position, text, color) in labels {
+            painter.text(
+                position,
+                egui::Align2::LEFT_CENTER,
+                text,
+                font.clone(),
+                color,
+            );
+        }
+    }
+    ///the full call path of a stack from the inspector, which starts at the node zoomed into
+    fn zoom_path(&self, stack: Vec<String>) -> Vec<String> {
+        match self.ui_state.zoom.split_last() {
+            Some((_, above)) => above.iter().cloned().chain(stack).collect(),
+            None => stack,
+        }
+    }
+    ///zooms the inspector, and the 3d graphs when they follow it, into a call path
+    ///a path the profile does not have zooms back out to the whole profile
+    pub fn set_zoom(&mut self, mut path: Vec<String>, frame: &mut eframe::Frame) {
+        if self.master_graph.zoomed(&path).is_none() {
+            path.clear();
+        }
+        if path == self.ui_state.zoom {
+            return;
+        }
+        self.ui_state.zoom = path;
+        if self.ui_state.zoom_graphs {
+            self.regen_all(frame);
+        } else {
+            self.regen_inspector(frame);
+        }
+    }
+    ///the call path zoomed into, each part can be clicked to zoom back out to it
+    pub fn breadcrumbs(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
+        if self.ui_state.zoom.is_empty() {
+            return;
+        }
+        let mut picked = None;
+        ui.horizontal_wrapped(|ui| {
+            if ui.button("All").clicked() {
+                picked = Some(0);
+            }
+            for (i, name) in self.ui_state.zoom.iter().enumerate() {
+                ui.label(">");
+                if ui.button(name).clicked() {
+                    picked = Some(i + 1);
+                }
+            }
+        });
+        let zoom_graphs = self.ui_state.zoom_graphs;
+        ui.checkbox(&mut self.ui_state.zoom_graphs, "Zoom 3D Graphs");
+        if zoom_graphs != self.ui_state.zoom_graphs {
+            self.regen_left_mesh(frame);
+            self.regen_right_mesh(frame);
+        }
+        if let Some(depth) = picked {
+            let path = self.ui_state.zoom[..depth].to_vec();
+            self.set_zoom(path, frame);
+        }
     }
-
     pub fn set_web_options(&mut self, ctx: &egui::Context) {
         if *DARK_MODE.lock() {
             self.ui_state.visual_options = egui::Visuals::dark()
@@ -232,85 +387,41 @@ impl App {
         let show_window = |ui: &mut egui::Ui| {
             ui.label("Selected Trace: ".to_owned() + &self.ui_state.modify_options.node.name);
 
-            if self.ui_state.modify_options.node.color.is_some() {
-                let c = self.ui_state.modify_options.node.color.unwrap();
+            if let Some(c) = self.ui_state.modify_options.node.color {
                 color = egui::epaint::Hsva::from_rgb([c[0], c[1], c[2]]);
             }
 
             let alpha = egui::widgets::color_picker::Alpha::Opaque;
             let _e = egui::widgets::color_picker::color_edit_button_hsva(ui, &mut color, alpha);
             if ui.button("Apply").clicked() {
-                if self.ui_state.modify_options.node.color.is_some() {
-                    self.master_graph.modify_color(
-                        self.ui_state.modify_options.node.name.clone(),
-                        self.ui_state.modify_options.node.color.unwrap(),
-                    );
+                if let Some(c) = self.ui_state.modify_options.node.color {
+                    Arc::make_mut(&mut self.master_graph)
+                        .modify_color(self.ui_state.modify_options.node.name.clone(), c);
                     self.regen_all(frame)
                 }
             }
             ui.horizontal(|ui| {
                 if ui.button("Hide others").clicked() {
-                    self.master_graph.modify_color(
-                        self.ui_state.modify_options.node.name.clone(),
-                        self.ui_state.modify_options.node.color.unwrap(),
-                    );
-                    let mut left_m =
-                        get_mesh_from_tree(&self.master_graph, &self.graph_left.mesh_options);
-                    let mut right_m =
-                        get_mesh_from_tree(&self.master_graph, &self.graph_right.mesh_options);
-                    for color in left_m.colors.iter_mut() {
-                        if *color != self.ui_state.modify_options.node.color.unwrap() {
-                            color[3] = 0.05;
-                        }
-                    }
-                    for color in right_m.colors.iter_mut() {
-                        if *color != self.ui_state.modify_options.node.color.unwrap() {
-                            color[3] = 0.05;
-                        }
-                    }
-                    self.graph_left.painter = Arc::new(Mutex::new(CubePainter::new(
-                        &frame.gl().unwrap(),
-                        &left_m.verts,
-                        &left_m.colors,
-                    )));
-                    self.graph_right.painter = Arc::new(Mutex::new(CubePainter::new(
-                        &frame.gl().unwrap(),
-                        &right_m.verts,
-                        &right_m.colors,
-                    )));
+                    let keep = self.ui_state.modify_options.node.color.unwrap();
+                    Arc::make_mut(&mut self.master_graph)
+                        .modify_color(self.ui_state.modify_options.node.name.clone(), keep);
+                    let fade = Some((keep, Fade::Hide));
+                    self.graph_left.job = Some(self.graph_job(&self.graph_left.mesh_options, fade));
+                    self.graph_right.job =
+                        Some(self.graph_job(&self.graph_right.mesh_options, fade));
                 }
                 if ui.button("Darken Others").clicked() {
                     self.ui_state.modify_options.highlighting_node =
                         !self.ui_state.modify_options.highlighting_node;
                     if self.ui_state.modify_options.highlighting_node {
-                        self.master_graph.modify_color(
-                            self.ui_state.modify_options.node.name.clone(),
-                            self.ui_state.modify_options.node.color.unwrap(),
-                        );
-                        let mut left_m =
-                            get_mesh_from_tree(&self.master_graph, &self.graph_left.mesh_options);
-                        let mut right_m =
-                            get_mesh_from_tree(&self.master_graph, &self.graph_right.mesh_options);
-                        for color in left_m.colors.iter_mut() {
-                            if *color != self.ui_state.modify_options.node.color.unwrap() {
-                                *color = [color[0] * 0.1, color[1] * 0.1, color[2] * 0.1, color[3]];
-                            }
-                        }
-                        for color in right_m.colors.iter_mut() {
-                            if *color != self.ui_state.modify_options.node.color.unwrap() {
-                                *color = [color[0] * 0.1, color[1] * 0.1, color[2] * 0.1, color[3]];
-                            }
-                        }
-                        self.graph_left.painter = Arc::new(Mutex::new(CubePainter::new(
-                            &frame.gl().unwrap(),
-                            &left_m.verts,
-                            &left_m.colors,
-                        )));
-                        self.graph_right.painter = Arc::new(Mutex::new(CubePainter::new(
-                            &frame.gl().unwrap(),
-                            &right_m.verts,
-                            &right_m.colors,
-                        )));
+                        let keep = self.ui_state.modify_options.node.color.unwrap();
+                        Arc::make_mut(&mut self.master_graph)
+                            .modify_color(self.ui_state.modify_options.node.name.clone(), keep);
+                        let fade = Some((keep, Fade::Darken));
+                        self.graph_left.job =
+                            Some(self.graph_job(&self.graph_left.mesh_options, fade));
+                        self.graph_right.job =
+                            Some(self.graph_job(&self.graph_right.mesh_options, fade));
                     } else {
                         self.regen_left_mesh(frame);
                         self.regen_right_mesh(frame);
@@ -326,17 +437,30 @@ impl App {
         self.ui_state.modify_options.node.color =
             Some([color.to_rgb()[0], color.to_rgb()[1], color.to_rgb()[2], 1.0]);
     }
-    pub fn regen_left_mesh(&mut self, frame: &mut eframe::Frame) {
-        let mesh_left = get_mesh_from_tree(&self.master_graph, &self.graph_left.mesh_options);
-        self.graph_left.painter = Arc::new(Mutex::new(CubePainter::new(
-            &frame.gl().unwrap(),
-            &mesh_left.verts,
-            &mesh_left.colors,
-        )));
+    ///starts rebuilding a 3d graph, `fade` is applied to every color but the one kept
+    fn graph_job(&self, options: &MeshOptions, fade: Option<([f32; 4], Fade)>) -> MeshJob {
+        let zoom = if self.ui_state.zoom_graphs {
+            self.ui_state.zoom.clone()
+        } else {
+            vec![]
+        };
+        let request = JobRequest::Graph {
+            options: options.clone(),
+            zoom,
+            search: self.ui_state.search_options.pattern.clone(),
+            fade,
+        };
+        MeshJob::spawn(self.master_graph.clone(), request)
+    }
+    pub fn regen_left_mesh(&mut self, _frame: &mut eframe::Frame) {
+        self.graph_left.job = Some(self.graph_job(&self.graph_left.mesh_options, None));
     }
     pub fn regen(&mut self, frame: &mut eframe::Frame, what: RegenWhat) {
         if what == RegenWhat::Inspector {
             self.regen_inspector(frame);
+        } else if what == RegenWhat::Left && self.colors_follow_left() {
+            self.recolor();
+            self.regen_all(frame);
         } else if what == RegenWhat::Left {
             self.regen_left_mesh(frame);
         } else if what == RegenWhat::Right {
@@ -345,35 +469,44 @@ impl App {
             self.regen_all(frame);
         }
     }
-    pub fn regen_right_mesh(&mut self, frame: &mut eframe::Frame) {
-        let mesh_right = get_mesh_from_tree(&self.master_graph, &self.graph_right.mesh_options);
-        self.graph_right.painter = Arc::new(Mutex::new(CubePainter::new(
-            &frame.gl().unwrap(),
-            &mesh_right.verts,
-            &mesh_right.colors,
-        )));
+    pub fn regen_right_mesh(&mut self, _frame: &mut eframe::Frame) {
+        self.graph_right.job = Some(self.graph_job(&self.graph_right.mesh_options, None));
     }
-    pub fn regen_inspector(&mut self, frame: &mut eframe::Frame) {
-        self.inspector_graph = match self.ui_state.inspector_options.across_metric {
-            AcrossMetric::Thread => build_thread_tree(
-                &self.master_graph,
-                self.ui_state.inspector_options.num_threads + 1,
-            ),
-            AcrossMetric::Time => build_time_tree(
-                &self.master_graph,
-                self.ui_state.inspector_options.time_range.start
-                    ..self.ui_state.inspector_options.time_range.end,
-            ),
+    pub fn regen_inspector(&mut self, _frame: &mut eframe::Frame) {
+        let request = JobRequest::Inspector {
+            options: self.ui_state.inspector_options.clone(),
+            selection: self.ui_state.inspector_selection.clone(),
+            compare: self.ui_state.inspector_compare.clone(),
+            zoom: self.ui_state.zoom.clone(),
+            search: self.ui_state.search_options.pattern.clone(),
         };
-
-        let mesh = get_rects_from_tree(&self.inspector_graph, &self.ui_state.inspector_options);
-
-        self.rect_painter = Arc::new(Mutex::new(CubePainter::new(
-            &frame.gl().unwrap(),
-            &mesh.verts,
-            &mesh.colors,
-        )));
-        self.inspector_mesh = mesh;
+        self.inspector_job = Some(MeshJob::spawn(self.master_graph.clone(), request));
+    }
+    ///swaps in the meshes of finished jobs, until then the old painters keep drawing
+    pub fn receive_meshes(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let gl = frame.gl().unwrap();
+        for graph in [&mut self.graph_left, &mut self.graph_right] {
+            if let Some(JobOutput::Graph(mesh)) = take_finished(&mut graph.job) {
+                graph.painter =
+                    Arc::new(Mutex::new(CubePainter::new(gl, &mesh.verts, &mesh.colors)));
+            }
+        }
+        if let Some(JobOutput::Inspector(tree, mesh, paths)) =
+            take_finished(&mut self.inspector_job)
+        {
+            self.rect_painter =
+                Arc::new(Mutex::new(CubePainter::new(gl, &mesh.verts, &mesh.colors)));
+            self.inspector_graph = tree;
+            self.inspector_mesh = mesh;
+            self.inspector_paths = paths;
+            self.update_matched_share();
+        }
+        if self.graph_left.job.is_some()
+            || self.graph_right.job.is_some()
+            || self.inspector_job.is_some()
+        {
+            ctx.request_repaint();
+        }
     }
     pub fn regen_all(&mut self, frame: &mut eframe::Frame) {
         if self.clear_options != ClearOptions::HasBeenCleared {
@@ -401,13 +534,13 @@ impl App {
         } else {
             panic!();
         }
-        let old_counter = counter.clone();
+        let old_counter = *counter;
         // Put the buttons and label on the same row:
         ui.horizontal(|ui| {
             ui.add(egui::Slider::new(counter, 1..=100).logarithmic(true));
             ui.add(egui::widgets::Label::new("Divisions"));
         });
-        if old_counter != counter.clone() {
+        if old_counter != *counter {
             self.regen(frame, what)
         }
     }
@@ -430,16 +563,115 @@ impl App {
         ui.horizontal(|ui| {
             ui.label("Metric");
             egui::containers::ComboBox::from_label("")
-                .selected_text(format!("{:?}", options))
+                .selected_text(options.name(&self.data_info))
                 .show_ui(ui, |ui| {
                     ui.selectable_value(options, DataChoices::Duration, "Duration");
                     ui.selectable_value(options, DataChoices::Value, "Value");
+                    for (i, name) in self.data_info.metrics.iter().enumerate() {
+                        ui.selectable_value(options, DataChoices::Metric(i), name);
+                    }
+                    for text in &self.ui_state.derived_options.expressions {
+                        if let Ok(expression) = Expression::parse(text, &self.data_info.metrics) {
+                            ui.selectable_value(options, DataChoices::Derived(expression), text);
+                        }
+                    }
                 });
         });
         if past_option != options.clone() {
+            if what_graph == WhatGraph::LeftGraph && self.colors_follow_left() {
+                self.recolor();
+            }
             self.regen_all(frame);
         }
     }
+    ///adds expressions over the metrics that can then be picked in the metric dropdowns
+    pub fn derived_metric_editor(&mut self, ui: &mut egui::Ui) {
+        let derived = &mut self.ui_state.derived_options;
+        ui.horizontal(|ui| {
+            ui.label("Derived Metric");
+            let edit = ui.text_edit_singleline(&mut derived.text);
+            let entered = edit.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            if ui.button("Add").clicked() || entered {
+                match Expression::parse(&derived.text, &self.data_info.metrics) {
+                    Ok(expression) => {
+                        if !derived.expressions.contains(&expression.text) {
+                            derived.expressions.push(expression.text);
+                        }
+                        derived.text.clear();
+                        derived.error = None;
+                    }
+                    Err(e) => derived.error = Some(e.to_string()),
+                }
+            }
+        });
+        if let Some(error) = &derived.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        let mut removed = None;
+        for (i, text) in derived.expressions.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(text);
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            derived.expressions.remove(i);
+        }
+    }
+    ///a search box for function names, matches are highlighted in every graph
+    pub fn search_box(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
+        let search = &mut self.ui_state.search_options;
+        let (text, regex) = (search.text.clone(), search.regex);
+        ui.horizontal(|ui| {
+            ui.label("Search");
+            ui.text_edit_singleline(&mut search.text);
+            ui.checkbox(&mut search.regex, "Regex");
+            if ui.button("Clear").clicked() {
+                search.text.clear();
+            }
+            if let Some(error) = &search.error {
+                ui.colored_label(egui::Color32::RED, error);
+            } else if search.pattern.is_some() {
+                ui.label(format!(
+                    "{:.2}% of this slice's {}",
+                    search.matched_share * 100.0,
+                    self.ui_state
+                        .inspector_options
+                        .data_metric
+                        .sized_by()
+                        .name(&self.data_info)
+                ));
+            }
+        });
+        if text == search.text && regex == search.regex {
+            return;
+        }
+        match NamePattern::parse(&search.text, search.regex) {
+            Ok(pattern) => {
+                search.pattern = pattern;
+                search.error = None;
+            }
+            //the last pattern that parsed stays highlighted while the regex is being typed
+            Err(e) => {
+                search.error = Some(e);
+                return;
+            }
+        }
+        self.regen_all(frame);
+    }
+    ///works out how much of the inspector's flamegraph the search matches
+    fn update_matched_share(&mut self) {
+        let search = &mut self.ui_state.search_options;
+        search.matched_share = match &search.pattern {
+            Some(pattern) => pattern.matched_share(
+                &self.inspector_graph,
+                &self.ui_state.inspector_options.data_metric,
+            ),
+            None => 0.0,
+        };
+    }
     pub fn across_dropdown(
         &mut self,
         frame: &mut eframe::Frame,
@@ -463,6 +695,7 @@ impl App {
                 .show_ui(ui, |ui| {
                     ui.selectable_value(options, AcrossMetric::Time, "Time");
                     ui.selectable_value(options, AcrossMetric::Thread, "Thread");
+                    ui.selectable_value(options, AcrossMetric::Process, "Process");
                 });
         });
 
@@ -470,35 +703,72 @@ impl App {
             self.regen_all(frame);
         }
     }
+    ///how events spanning several time slices share their value between them
+    pub fn attribution_dropdown(
+        &mut self,
+        frame: &mut eframe::Frame,
+        ui: &mut egui::Ui,
+        what_graph: WhatGraph,
+    ) {
+        //there are two sets of options, one for the left graph, the other for the right
+        let options;
+        if what_graph == WhatGraph::LeftGraph {
+            options = &mut self.graph_left.mesh_options.attribution;
+        } else if what_graph == WhatGraph::RightGraph {
+            options = &mut self.graph_right.mesh_options.attribution;
+        } else {
+            panic!();
+        }
+        let past_option = *options;
+        ui.horizontal(|ui| {
+            ui.label("Attribution");
+            egui::containers::ComboBox::from_label("  ")
+                .selected_text(format!("{:?}", options))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(options, Attribution::Proportional, "Proportional");
+                    ui.selectable_value(options, Attribution::Start, "Start");
+                    ui.selectable_value(options, Attribution::End, "End");
+                });
+        });
+        if past_option != *options {
+            //trend colors are fitted to the left graph's slices, which the attribution changes
+            if what_graph == WhatGraph::LeftGraph && self.colors_follow_left() {
+                self.recolor();
+            }
+            self.regen_all(frame);
+        }
+    }
     pub fn salt_drag_value(&mut self, frame: &mut eframe::Frame, ui: &mut egui::Ui) {
-        let salt = &mut self.master_graph.color_salt;
-        let past_salt = salt.clone();
+        //edited on a copy, the tree is only written to when it changes
+        let mut salt = self.master_graph.color_salt;
         ui.horizontal(|ui| {
-            ui.add(egui::DragValue::new(salt).speed(1));
+            ui.add(egui::DragValue::new(&mut salt).speed(1));
             ui.add(egui::widgets::Label::new("Seed"));
         });
-        if past_salt != salt.clone() {
-            self.master_graph.new_color_scheme();
+        if salt != self.master_graph.color_salt {
+            Arc::make_mut(&mut self.master_graph).color_salt = salt;
+            self.recolor();
             self.regen_all(frame);
         }
     }
     pub fn color_dropdown(&mut self, frame: &mut eframe::Frame, ui: &mut egui::Ui) {
-        //there are two sets of options, one for the left graph, the other for the right
-        let color = &mut self.master_graph.color_scheme;
-        let past_color = color.clone();
+        //edited on a copy, the tree is only written to when it changes
+        let mut color = self.master_graph.color_scheme;
         ui.horizontal(|ui| {
             ui.label("Color Scheme");
             egui::containers::ComboBox::from_label("")
                 .selected_text(format!("{:?}", color))
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(color, ColorScheme::Flame, "Flame");
-                    ui.selectable_value(color, ColorScheme::Ice, "Ice");
-                    ui.selectable_value(color, ColorScheme::Greyscale, "Greyscale");
-                    ui.selectable_value(color, ColorScheme::Rainbow, "Rainbow");
+                    ui.selectable_value(&mut color, ColorScheme::Flame, "Flame");
+                    ui.selectable_value(&mut color, ColorScheme::Ice, "Ice");
+                    ui.selectable_value(&mut color, ColorScheme::Greyscale, "Greyscale");
+                    ui.selectable_value(&mut color, ColorScheme::Rainbow, "Rainbow");
+                    ui.selectable_value(&mut color, ColorScheme::Trend, "Trend");
                 });
         });
-        if past_color != color.clone() {
-            self.master_graph.new_color_scheme();
+        if color != self.master_graph.color_scheme {
+            Arc::make_mut(&mut self.master_graph).color_scheme = color;
+            self.recolor();
             self.regen_all(frame);
         }
     }
@@ -519,7 +789,7 @@ impl App {
         } else {
             panic!();
         }
-        let old_counter = counter.clone();
+        let old_counter = *counter;
         ui.checkbox(counter, "Spacing");
         if old_counter != *counter {
             self.regen(frame, what);
@@ -541,18 +811,21 @@ impl App {
             what = RegenWhat::Right;
         } else {
             // what_graph == WhatGraph::Inspector
-            counter = &mut self.ui_state.inspector_options.time_range;
+            let InspectorSelection::Time(range) = &mut self.ui_state.inspector_selection else {
+                return;
+            };
+            counter = range;
             what = RegenWhat::Inspector;
         }
         let old_counter = counter.clone();
         ui.horizontal(|ui| {
             ui.add(egui::Slider::new(
                 &mut counter.start,
-                self.data_info.start..=self.data_info.end - 1,
+                self.data_info.start..=self.data_info.end.saturating_sub(1),
             ));
             ui.add(egui::widgets::Label::new("Start Time"));
         });
-        counter.start = std::cmp::min(counter.start, counter.end - 1000);
+        counter.start = std::cmp::min(counter.start, counter.end.saturating_sub(1000));
         if old_counter != counter.clone() {
             self.regen(frame, what)
         }
@@ -573,14 +846,17 @@ impl App {
             what = RegenWhat::Right;
         } else {
             // what_graph == WhatGraph::Inspector
-            counter = &mut self.ui_state.inspector_options.time_range;
+            let InspectorSelection::Time(range) = &mut self.ui_state.inspector_selection else {
+                return;
+            };
+            counter = range;
             what = RegenWhat::Inspector;
         }
         let old_counter = counter.clone();
         ui.horizontal(|ui| {
             ui.add(egui::Slider::new(
                 &mut counter.end,
-                self.data_info.start..=self.data_info.end - 1,
+                self.data_info.start..=self.data_info.end.saturating_sub(1),
             ));
             ui.add(egui::widgets::Label::new("End Time"));
         });
@@ -9,7 +9,7 @@ pub struct CubePainter {
     vertex_count: usize,
 }
 impl CubePainter {
-    pub fn new(gl: &glow::Context, verticies: &Vec<[f32; 3]>, colors: &Vec<[f32; 4]>) -> Self {
+    pub fn new(gl: &glow::Context, verticies: &[[f32; 3]], colors: &[[f32; 4]]) -> Self {
         unsafe {
             let program = create_program(gl, VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE);
             let (_vertex_buffer, vertex_array) = create_vertex_buffer(gl, verticies, colors);
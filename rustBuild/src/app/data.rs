@@ -1,38 +1,92 @@
+use crate::app::derived::*;
+use crate::app::jobs::JobProgress;
+use crate::app::search::{darken, NamePattern};
 use crate::app::tree::*;
+use std::collections::VecDeque;
 use std::ops::Range;
-use traversal::Bft;
 pub const LENGTH_MOD: f32 = 1.9;
 pub const LENGTH_OFFSET: f32 = 0.75;
 pub const BREDTH_MOD: f32 = 1.8;
 pub const BREDTH_OFFSET: f32 = 0.9;
 pub const VERTS_IN_RECT: usize = 6;
-pub const _VERTS_IN_CUBE: usize = 36;
+pub const VERTS_IN_CUBE: usize = 36;
 const DEFUALT_DIVISONS: usize = 5;
 
 /**  Data deals with the transformation of input (Currently JSON) to verticies
  *  
 */
-#[derive(Debug, Clone, PartialEq)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
 ///DataChoices allows users to specify which type of data should be graphed
 pub enum DataChoices {
     Duration,
     Value,
+    ///a named metric, indexed the same as `TreeInfo.metrics`
+    Metric(usize),
+    ///an expression over the other metrics, worked out separately for every node and slice
+    Derived(Expression),
 }
-#[derive(Debug, Clone, PartialEq)]
+impl DataChoices {
+    ///how much of this metric is in a set of values
+    pub fn of(&self, values: &TraceValues) -> f64 {
+        match self {
+            DataChoices::Duration => values.dur as f64,
+            DataChoices::Value => values.value as f64,
+            DataChoices::Metric(index) => values.metric(*index) as f64,
+            DataChoices::Derived(expression) => expression.evaluate(values),
+        }
+    }
+    ///the name shown to users, metrics are named by the trace file
+    pub fn name(&self, info: &TreeInfo) -> String {
+        match self {
+            DataChoices::Metric(index) => info
+                .metrics
+                .get(*index)
+                .cloned()
+                .unwrap_or_else(|| format!("Metric {}", index)),
+            DataChoices::Derived(expression) => expression.text.clone(),
+            _ => format!("{:?}", self),
+        }
+    }
+    ///what bars are sized by, a child's bar has to fit inside its parent's but ratios do not add
+    ///up that way, so derived expressions are sized by duration and shown by color instead
+    pub fn sized_by(&self) -> DataChoices {
+        match self {
+            DataChoices::Derived(_) => DataChoices::Duration,
+            other => other.clone(),
+        }
+    }
+}
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
 ///DataChoices allows users to specify which type of data should be graphed
 pub enum AcrossMetric {
     Time,
     Thread,
+    Process,
+}
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq)]
+///Attribution decides which time slices get the value of an event that spans several
+pub enum Attribution {
+    ///split by how much of the event's duration falls in each slice
+    Proportional,
+    ///all of it goes to the slice the event starts in
+    Start,
+    ///all of it goes to the slice the event ends in
+    End,
 }
 ///MeshOptions allow custom generation of the graphs
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
 pub struct MeshOptions {
     pub bar_spacing: bool,
     pub has_changed: bool,
     pub time_range: Range<u64>,
     pub data_metric: DataChoices,
     pub across_metric: AcrossMetric,
+    pub attribution: Attribution,
     pub num_graphs: usize,
     pub num_threads: usize,
+    pub num_processes: usize,
+    ///the process of each thread, from `ThreadRegistry::thread_processes`
+    pub thread_processes: Vec<usize>,
 }
 impl MeshOptions {
     pub fn new_3d(info: &TreeInfo) -> Self {
@@ -42,8 +96,11 @@ impl MeshOptions {
             num_graphs: DEFUALT_DIVISONS,
             time_range: info.start..info.end,
             num_threads: info.num_threads,
+            num_processes: info.threads.num_processes(),
+            thread_processes: info.threads.thread_processes(),
             data_metric: DataChoices::Duration,
             across_metric: AcrossMetric::Time,
+            attribution: Attribution::Proportional,
         }
     }
     pub fn new_2d(info: &TreeInfo) -> Self {
@@ -52,79 +109,201 @@ impl MeshOptions {
             has_changed: false,
             num_graphs: 1,
             num_threads: info.num_threads,
+            num_processes: info.threads.num_processes(),
+            thread_processes: info.threads.thread_processes(),
             time_range: info.start..(info.start + (info.end - info.start) / 5),
             data_metric: DataChoices::Duration,
             across_metric: AcrossMetric::Time,
+            attribution: Attribution::Proportional,
+        }
+    }
+    ///how many slices the graph is split into
+    pub fn num_divisions(&self) -> usize {
+        match self.across_metric {
+            AcrossMetric::Time => self.num_graphs,
+            AcrossMetric::Thread => self.num_threads,
+            AcrossMetric::Process => self.num_processes,
+        }
+    }
+    ///what slice `n` of the graph is called, threads and processes are named by the file
+    pub fn division_name(&self, n: usize, info: &TreeInfo) -> String {
+        match self.across_metric {
+            AcrossMetric::Time => format!("Time Slice {}", n),
+            AcrossMetric::Thread => info.threads.thread_name(n),
+            AcrossMetric::Process => info.threads.process_name(n),
+        }
+    }
+}
+///what the inspector shows, picked by clicking a slice of one of the 3d graphs
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub enum InspectorSelection {
+    Time(Range<u64>),
+    ///a thread by its index in `ThreadRegistry.entries`
+    Thread(usize),
+    ///a process by its index in `ThreadRegistry.processes`
+    Process(usize),
+}
+impl InspectorSelection {
+    ///the selection for slice `n` of a graph built with `options`, counted from 0
+    pub fn of_division(options: &MeshOptions, n: usize) -> Self {
+        match options.across_metric {
+            AcrossMetric::Time => {
+                InspectorSelection::Time(time_slice(&options.time_range, options.num_graphs, n))
+            }
+            AcrossMetric::Thread => InspectorSelection::Thread(n),
+            AcrossMetric::Process => InspectorSelection::Process(n),
+        }
+    }
+    ///a short description for labels
+    pub fn describe(&self, info: &TreeInfo) -> String {
+        match self {
+            InspectorSelection::Time(range) => format!("time {} to {}", range.start, range.end),
+            InspectorSelection::Thread(thread) => info.threads.thread_name(*thread),
+            InspectorSelection::Process(process) => info.threads.process_name(*process),
+        }
+    }
+    ///the flamegraph of everything selected
+    pub fn build_tree(&self, master: &MasterTree, options: &MeshOptions) -> Tree {
+        match self {
+            InspectorSelection::Time(range) => {
+                build_time_tree(master, range.clone(), options.attribution)
+            }
+            InspectorSelection::Thread(thread) => build_thread_tree(master, *thread),
+            InspectorSelection::Process(process) => {
+                build_process_tree(master, *process, &options.thread_processes)
+            }
         }
     }
 }
-pub fn get_mesh_from_tree(graph: &MasterTree, mesh_options: &MeshOptions) -> Mesh {
-    let mut mesh = Mesh {
-        verts: vec![],
-        colors: vec![],
-        indicies: vec![],
+///builds the 3d graph, None if the progress was cancelled part way through
+///functions the search does not match are darkened, a non-empty `zoom` graphs only the node at
+///the end of that call path and what it calls, and nothing when the profile does not have it
+pub fn get_mesh_from_tree(
+    graph: &MasterTree,
+    zoom: &[String],
+    mesh_options: &MeshOptions,
+    search: Option<&NamePattern>,
+    progress: &JobProgress,
+) -> Option<Mesh> {
+    let mut mesh = Mesh::default();
+    let Some(top) = graph.zoomed(zoom) else {
+        return Some(mesh);
     };
     let root_overlaps = match mesh_options.across_metric {
-        AcrossMetric::Time => graph
-            .root
-            .time_overlaps(mesh_options.num_graphs, mesh_options.time_range.clone()),
-        AcrossMetric::Thread => graph.root.tread_overlaps(mesh_options.num_threads),
+        AcrossMetric::Time => top.time_overlaps(
+            mesh_options.num_graphs,
+            mesh_options.time_range.clone(),
+            mesh_options.attribution,
+        ),
+        AcrossMetric::Thread => top.tread_overlaps(mesh_options.num_threads),
+        AcrossMetric::Process => {
+            top.process_overlaps(&mesh_options.thread_processes, mesh_options.num_processes)
+        }
     };
     let Some(root_overlaps)  = root_overlaps else {
-        return mesh;
-    };
-    let max_size = match mesh_options.data_metric {
-        DataChoices::Duration => root_overlaps.iter().map(|i| i.dur).max().unwrap_or(0),
-        DataChoices::Value => root_overlaps.iter().map(|i| i.value).max().unwrap_or(0),
+        return Some(mesh);
     };
+    let sized_by = mesh_options.data_metric.sized_by();
+    let max_size = root_overlaps
+        .iter()
+        .map(|i| sized_by.of(i))
+        .fold(0.0, f64::max);
 
-    let num_division = match mesh_options.across_metric {
-        AcrossMetric::Time => mesh_options.num_graphs,
-        AcrossMetric::Thread => mesh_options.num_threads,
-    };
-    let mut c = 0;
+    let num_division = mesh_options.num_divisions();
+    progress.set_total(count_nodes(top));
+    //the root is never drawn, a node zoomed into starts one layer up as the bottom layer
+    let depth = if zoom.is_empty() { 1 } else { 2 };
+    let mut shades = vec![];
     tree_to_verts(&mut MeshBuilder {
-        current_node: &graph.root,
+        current_node: top,
         options: mesh_options,
-        starting_dur_offset: vec![vec![0, 0]; num_division],
-        starting_val_offset: vec![vec![0, 0]; num_division],
-        depth: 1,
+        sized_by,
+        starting_offset: vec![vec![0.0; depth + 1]; num_division],
+        depth,
         max_bar_size: max_size,
         mesh: &mut mesh,
-        counter: &mut c,
+        shades: &mut shades,
+        progress,
         num_divisions: num_division,
+        search,
     });
-    mesh
+    if progress.is_cancelled() {
+        return None;
+    }
+    //the colors are only known once the largest value is
+    let largest = shades.iter().map(|shade| shade.1).fold(0.0, f64::max);
+    for (at, value, dim) in shades {
+        let mut color = derived_color(value, largest);
+        if dim {
+            color = darken(color);
+        }
+        mesh.colors[at..at + VERTS_IN_CUBE].fill(color);
+    }
+    Some(mesh)
+}
+fn count_nodes(node: &MasterNode) -> usize {
+    1 + node.children.iter().map(count_nodes).sum::<usize>()
 }
 ///called for every tree in the list, transforms the tree into a vec of verticies, indicies, and colors
 struct MeshBuilder<'a> {
     current_node: &'a MasterNode,
     options: &'a MeshOptions,
-    starting_dur_offset: Vec<Vec<u64>>,
-    starting_val_offset: Vec<Vec<u64>>,
+    sized_by: DataChoices,
+    starting_offset: Vec<Vec<f64>>,
     depth: usize,
-    max_bar_size: u64,
+    max_bar_size: f64,
     mesh: &'a mut Mesh,
-    counter: &'a mut usize,
+    ///for a derived metric, the first color of each cube, its value and if the search dims it
+    shades: &'a mut Vec<(usize, f64, bool)>,
+    progress: &'a JobProgress,
     num_divisions: usize,
+    search: Option<&'a NamePattern>,
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Mesh {
     pub verts: Vec<[f32; 3]>,
     pub colors: Vec<[f32; 4]>,
-    pub indicies: Vec<u32>,
+}
+///where the inspector's rects came from, nodes are counted breadth first from the root
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Default)]
+pub struct PathIndex {
+    ///the node behind each rect, in the order the rects were drawn
+    pub rects: Vec<usize>,
+    ///the parent of each node, the root is its own parent
+    pub parents: Vec<usize>,
+}
+impl PathIndex {
+    ///the nodes from below the root down to `node`, outermost first
+    pub fn path(&self, node: usize) -> Vec<usize> {
+        let mut path = vec![];
+        let mut at = node;
+        while at != 0 && at < self.parents.len() {
+            path.push(at);
+            at = self.parents[at];
+        }
+        path.reverse();
+        path
+    }
 }
 fn tree_to_verts<'a>(builder: &mut MeshBuilder<'a>) {
-    *builder.counter += 1;
+    if builder.progress.is_cancelled() {
+        return;
+    }
+    builder.progress.step();
 
     let overlaps = match builder.options.across_metric {
         AcrossMetric::Time => builder.current_node.time_overlaps(
             builder.options.num_graphs,
             builder.options.time_range.clone(),
+            builder.options.attribution,
         ),
         AcrossMetric::Thread => builder
             .current_node
             .tread_overlaps(builder.options.num_threads),
+        AcrossMetric::Process => builder.current_node.process_overlaps(
+            &builder.options.thread_processes,
+            builder.options.num_processes,
+        ),
     };
     let Some(overlaps)  = overlaps else {
         return ;
@@ -132,12 +311,11 @@ fn tree_to_verts<'a>(builder: &mut MeshBuilder<'a>) {
 
     //add this overlap to the layer below's offset
     for value in overlaps.iter().enumerate() {
-        builder.starting_dur_offset[value.0][builder.depth - 1] += value.1.dur;
-        builder.starting_val_offset[value.0][builder.depth - 1] += value.1.value;
+        builder.starting_offset[value.0][builder.depth - 1] += builder.sized_by.of(value.1);
     }
     //build the node to verts
     if builder.current_node.color.is_some() && builder.depth > 1 {
-        let mut result = verts_from_overlaps(&builder, &overlaps);
+        let mut result = verts_from_overlaps(builder, &overlaps);
         builder.mesh.verts.append(&mut result.0);
         builder.mesh.colors.append(&mut result.1);
     }
@@ -146,19 +324,13 @@ fn tree_to_verts<'a>(builder: &mut MeshBuilder<'a>) {
         builder.depth += 1;
 
         //push a duplicate on the offset stacks
-        for graph_value in &mut builder.starting_dur_offset {
-            graph_value.push(*graph_value.last().unwrap_or(&0));
-        }
-        for graph_value in &mut builder.starting_val_offset {
-            graph_value.push(*graph_value.last().unwrap_or(&0));
+        for graph_value in &mut builder.starting_offset {
+            graph_value.push(*graph_value.last().unwrap_or(&0.0));
         }
         tree_to_verts(builder);
         //if we need to pop, do so until we are at the right level
-        while builder.starting_dur_offset[0].len() > builder.depth {
-            for value in &mut builder.starting_dur_offset {
-                value.pop();
-            }
-            for value in &mut builder.starting_val_offset {
+        while builder.starting_offset[0].len() > builder.depth {
+            for value in &mut builder.starting_offset {
                 value.pop();
             }
         }
@@ -181,44 +353,37 @@ fn tree_to_verts<'a>(builder: &mut MeshBuilder<'a>) {
 }
 ///returns a single block for one node in the tree
 fn verts_from_overlaps(
-    builder: &MeshBuilder<'_>,
-    overlaps: &Vec<TraceValues>,
+    builder: &mut MeshBuilder<'_>,
+    overlaps: &[TraceValues],
 ) -> (Vec<[f32; 3]>, Vec<[f32; 4]>) {
     let mut colors: Vec<[f32; 4]> = vec![];
     let mut block_size = vec![];
     let mut offset = vec![];
-    if builder.options.data_metric == DataChoices::Duration {
-        for overlap in overlaps.iter().enumerate() {
-            block_size.push(overlap.1.dur as f32 / builder.max_bar_size as f32);
-            offset.push(
-                builder.starting_dur_offset[overlap.0][builder.depth] as f32
-                    / builder.max_bar_size as f32
-                    - 0.9,
-            );
-        }
-    }
-    if builder.options.data_metric == DataChoices::Value {
-        for overlap in overlaps.iter().enumerate() {
-            block_size.push(overlap.1.value as f32 / builder.max_bar_size as f32);
-            offset.push(
-                builder.starting_val_offset[overlap.0][builder.depth] as f32
-                    / builder.max_bar_size as f32
-                    - 0.9,
-            );
-        }
+    for overlap in overlaps.iter().enumerate() {
+        block_size.push((builder.sized_by.of(overlap.1) / builder.max_bar_size) as f32);
+        offset.push(
+            (builder.starting_offset[overlap.0][builder.depth] / builder.max_bar_size) as f32 - 0.9,
+        );
     }
     let depth1 = 1.0 / -2.0_f32.powf(0.1 * builder.depth as f32) + 1.0;
     let depth2 = 1.0 / -2.0_f32.powf(0.1 * (builder.depth + 1) as f32) + 1.0;
     //these values help adjust the verts into a {-1,-1 to 1,1} cube
     let mut result = vec![];
-    let spacing;
-    if builder.options.bar_spacing {
-        spacing = 1.0;
+    let spacing = if builder.options.bar_spacing {
+        1.0
     } else {
-        spacing = 0.0;
-    }
+        0.0
+    };
+    let dim = builder
+        .search
+        .map_or(false, |search| !search.matches(&builder.current_node.name));
     for overlap in 0..overlaps.len() {
         if block_size[overlap] > 0.0 {
+            if let DataChoices::Derived(expression) = &builder.options.data_metric {
+                let at = builder.mesh.colors.len() + result.len();
+                let value = expression.evaluate(&overlaps[overlap]);
+                builder.shades.push((at, value, dim));
+            }
             result.append(&mut get_points_for_cube(
                 [
                     LENGTH_OFFSET + offset[overlap] * LENGTH_MOD,
@@ -235,8 +400,12 @@ fn verts_from_overlaps(
         }
     }
 
+    let mut color = builder.current_node.color.unwrap();
+    if dim {
+        color = darken(color);
+    }
     for _vert in &result {
-        colors.push(builder.current_node.color.unwrap());
+        colors.push(color);
     }
     (result, colors)
 }
@@ -281,27 +450,29 @@ fn get_rect_from_points(
 }
 ///this method could use conservative rasterazation, currently hides nodes smaller than 2 pixels wide or so
 ///This method is used to display flamegraphs in the inspector, similar to get_mesh_from_graphs but 2d
-pub fn get_rects_from_tree(flamegraph: &Tree, options: &MeshOptions) -> Mesh {
-    let mut max_bar_size = 0;
+///The path index is built on the same walk, so a rect can be traced back to its call path
+pub fn get_rects_from_tree(flamegraph: &Tree, options: &MeshOptions) -> (Mesh, PathIndex) {
+    let sized_by = options.data_metric.sized_by();
+    let max_bar_size = sized_by.of(&flamegraph.root.values);
     let mut verticies: Vec<[f32; 3]> = vec![];
-    let indicies: Vec<u32> = vec![];
     let mut colors: Vec<[f32; 4]> = vec![];
-    let iter = Bft::new(&flamegraph.root, |tree| tree.children.iter());
-    for block in iter.map(|(depth, node)| (depth, node)) {
-        if block.1.color.is_some() {
-            let mut block_size = 0.0;
-            let mut off = 1;
-            if options.data_metric == DataChoices::Duration {
-                max_bar_size = flamegraph.root.values.dur;
-                block_size = block.1.values.dur as f32 / max_bar_size as f32;
-                off = block.1.offsets.dur;
-            }
-            if options.data_metric == DataChoices::Value {
-                max_bar_size = flamegraph.root.values.value;
-                block_size = block.1.values.value as f32 / max_bar_size as f32;
-                off = block.1.offsets.value;
-            }
-            let offset = (off as f32 / max_bar_size as f32) - 0.9;
+    let mut paths = PathIndex {
+        rects: vec![],
+        parents: vec![0],
+    };
+    //walked breadth first like the lookups in ui_helper, each child starts where the last ended
+    let mut queue = VecDeque::from([(0, &flamegraph.root, 0.0, 0)]);
+    while let Some(block) = queue.pop_front() {
+        let mut child_offset = block.2;
+        for child in &block.1.children {
+            queue.push_back((block.0 + 1, child, child_offset, paths.parents.len()));
+            paths.parents.push(block.3);
+            child_offset += sized_by.of(&child.values);
+        }
+        if let Some(color) = block.1.color {
+            paths.rects.push(block.3);
+            let block_size = (sized_by.of(&block.1.values) / max_bar_size) as f32;
+            let offset = (block.2 / max_bar_size) as f32 - 0.9;
             //if the block size is too small, break (to prevent flickering)f
 
             // if block_size <= 1.0 / display_width {
@@ -317,7 +488,7 @@ pub fn get_rects_from_tree(flamegraph: &Tree, options: &MeshOptions) -> Mesh {
                 2,
             );
             for _vert in &result {
-                colors.push(block.1.color.unwrap());
+                colors.push(color);
             }
             verticies.append(&mut result);
         }
@@ -335,9 +506,251 @@ pub fn get_rects_from_tree(flamegraph: &Tree, options: &MeshOptions) -> Mesh {
     //     ]);
     //     i += 1;
     // }
-    Mesh {
-        verts: verticies,
-        colors: colors,
-        indicies: indicies,
+    (
+        Mesh {
+            verts: verticies,
+            colors,
+        },
+        paths,
+    )
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///one call of `f` on each (pid, tid), with the value 1, 2, 4... so sums show which were kept
+    fn threads_of(ids: &[(usize, usize)]) -> (MasterTree, TreeInfo) {
+        let traces: Vec<Trace> = ids
+            .iter()
+            .enumerate()
+            .map(|(id, (pid, tid))| Trace {
+                start: id as u64 * 10,
+                value: 1 << id,
+                pid: *pid,
+                tid: *tid,
+                ..test_trace("f")
+            })
+            .collect();
+        grow_master_tree(&traces)
+    }
+    fn across(info: &TreeInfo, across_metric: AcrossMetric) -> MeshOptions {
+        MeshOptions {
+            across_metric,
+            ..MeshOptions::new_3d(info)
+        }
+    }
+    ///value of what the inspector would show after clicking slice `n`
+    fn selected_value(master: &MasterTree, options: &MeshOptions, n: usize) -> u64 {
+        InspectorSelection::of_division(options, n)
+            .build_tree(master, options)
+            .root
+            .values
+            .value
+    }
+
+    #[test]
+    fn first_and_last_thread_are_selectable() {
+        let (master, info) = threads_of(&[(0, 900), (0, 7), (0, 12345)]);
+        let options = across(&info, AcrossMetric::Thread);
+        let last = options.num_divisions() - 1;
+        assert_eq!(last, 2);
+        assert_eq!(
+            InspectorSelection::of_division(&options, 0),
+            InspectorSelection::Thread(0)
+        );
+        assert_eq!(selected_value(&master, &options, 0), 1);
+        assert_eq!(
+            InspectorSelection::of_division(&options, last),
+            InspectorSelection::Thread(2)
+        );
+        assert_eq!(selected_value(&master, &options, last), 4);
+    }
+    #[test]
+    fn first_and_last_process_are_selectable() {
+        let (master, info) = threads_of(&[(3, 1), (8, 1), (3, 2)]);
+        let options = across(&info, AcrossMetric::Process);
+        assert_eq!(options.num_divisions(), 2);
+        assert_eq!(selected_value(&master, &options, 0), 1 + 4);
+        assert_eq!(selected_value(&master, &options, 1), 2);
+    }
+    #[test]
+    fn time_slices_cover_the_whole_range() {
+        let (_, info) = threads_of(&[(0, 0), (0, 0), (0, 0), (0, 0)]);
+        let options = across(&info, AcrossMetric::Time);
+        let first = InspectorSelection::of_division(&options, 0);
+        let last = InspectorSelection::of_division(&options, options.num_graphs - 1);
+        assert_eq!(first, InspectorSelection::Time(0..8));
+        assert_eq!(last, InspectorSelection::Time(32..40));
+    }
+    #[test]
+    fn time_slices_match_the_graph_slices() {
+        //events at 0, 3, 6 and 9 worth 1, 2, 4 and 8, ten units do not split evenly in three
+        let traces: Vec<Trace> = (0..4)
+            .map(|i| Trace {
+                start: i * 3,
+                dur: 1,
+                value: 1 << i,
+                ..test_trace("f")
+            })
+            .collect();
+        let (master, info) = grow_master_tree(&traces);
+        let options = MeshOptions {
+            time_range: 0..10,
+            num_graphs: 3,
+            attribution: Attribution::Start,
+            ..MeshOptions::new_3d(&info)
+        };
+        let slice = |n| InspectorSelection::of_division(&options, n);
+        assert_eq!(slice(0), InspectorSelection::Time(0..3));
+        assert_eq!(slice(2), InspectorSelection::Time(6..9));
+        for n in 0..2 {
+            let (InspectorSelection::Time(this), InspectorSelection::Time(next)) =
+                (slice(n), slice(n + 1))
+            else {
+                unreachable!();
+            };
+            assert_eq!(this.end, next.start);
+        }
+        let graph = master.root.children[0]
+            .time_overlaps(3, options.time_range.clone(), options.attribution)
+            .unwrap();
+        for (n, overlap) in graph.iter().enumerate() {
+            assert_eq!(selected_value(&master, &options, n), overlap.value);
+        }
+        //the event at 9 is past the last slice in both
+        assert_eq!(selected_value(&master, &options, 2), 4);
+    }
+    #[test]
+    fn rects_trace_back_to_their_call_path() {
+        let traces = [
+            test_trace("main;a;x"),
+            Trace {
+                start: 10,
+                ..test_trace("main;b")
+            },
+        ];
+        let (master, info) = grow_master_tree(&traces);
+        let options = MeshOptions::new_2d(&info);
+        let tree = InspectorSelection::Time(info.start..info.end).build_tree(&master, &options);
+        let (mesh, paths) = get_rects_from_tree(&tree, &options);
+        assert_eq!(paths.rects.len(), mesh.verts.len() / VERTS_IN_RECT);
+        let stacks: Vec<String> = paths
+            .rects
+            .iter()
+            .map(|n| crate::app::ui_helper::call_stack(&tree, &paths, *n).join(";"))
+            .collect();
+        assert_eq!(stacks, ["main", "main;a", "main;b", "main;a;x"]);
+    }
+    ///a parent with a low value / dur ratio around children with high ones
+    fn ratio_tree() -> (MasterTree, TreeInfo, DataChoices) {
+        let traces = [
+            Trace {
+                dur: 100,
+                ..test_trace("main")
+            },
+            Trace {
+                start: 100,
+                dur: 5,
+                value: 40,
+                ..test_trace("main;a")
+            },
+            Trace {
+                start: 105,
+                dur: 5,
+                value: 90,
+                ..test_trace("main;b")
+            },
+        ];
+        let (master, info) = grow_master_tree(&traces);
+        let ratio = DataChoices::Derived(Expression::parse("value / dur", &[]).unwrap());
+        (master, info, ratio)
+    }
+    ///the lowest and highest x of each rect
+    fn rect_spans(mesh: &Mesh) -> Vec<(f32, f32)> {
+        mesh.verts
+            .chunks(VERTS_IN_RECT)
+            .map(|rect| {
+                let xs = rect.iter().map(|v| v[0]);
+                (
+                    xs.clone().fold(f32::MAX, f32::min),
+                    xs.fold(f32::MIN, f32::max),
+                )
+            })
+            .collect()
+    }
+    #[test]
+    fn ratio_children_fit_inside_their_parents() {
+        let (master, info, ratio) = ratio_tree();
+        let options = MeshOptions {
+            data_metric: ratio,
+            ..MeshOptions::new_2d(&info)
+        };
+        let mut tree = InspectorSelection::Time(info.start..info.end).build_tree(&master, &options);
+        let DataChoices::Derived(expression) = &options.data_metric else {
+            unreachable!();
+        };
+        shade_tree(&mut tree, expression);
+        let main = &tree.root.children[0];
+        assert_eq!(main.children[1].color, Some(derived_color(1.0, 1.0)));
+        let (mesh, paths) = get_rects_from_tree(&tree, &options);
+        let spans = rect_spans(&mesh);
+        assert_eq!(spans.len(), 3);
+        for (rect, node) in paths.rects.iter().enumerate() {
+            let parent = paths.parents[*node];
+            let Some(parent_rect) = paths.rects.iter().position(|n| *n == parent) else {
+                continue;
+            };
+            let (child, parent) = (spans[rect], spans[parent_rect]);
+            assert!(child.0 >= parent.0 - 1e-6 && child.1 <= parent.1 + 1e-6);
+        }
+    }
+    #[test]
+    fn ratios_color_3d_bars_sized_by_duration() {
+        let (master, info, ratio) = ratio_tree();
+        let by_duration = MeshOptions {
+            num_graphs: 1,
+            ..MeshOptions::new_3d(&info)
+        };
+        let by_ratio = MeshOptions {
+            data_metric: ratio,
+            ..by_duration.clone()
+        };
+        let progress = JobProgress::default();
+        let duration = get_mesh_from_tree(&master, &[], &by_duration, None, &progress).unwrap();
+        let ratios = get_mesh_from_tree(&master, &[], &by_ratio, None, &progress).unwrap();
+        assert_eq!(ratios.verts, duration.verts);
+        //b has the largest ratio, 90 / 5
+        let cubes: Vec<[f32; 4]> = ratios.colors.chunks(VERTS_IN_CUBE).map(|c| c[0]).collect();
+        assert_eq!(cubes.len(), 3);
+        assert_eq!(cubes[2], derived_color(1.0, 1.0));
+        assert_eq!(cubes[1], derived_color(8.0, 18.0));
+    }
+    #[test]
+    fn zoomed_graphs_match_a_profile_of_the_node_alone() {
+        let trace = |path, start| Trace {
+            start,
+            ..test_trace(path)
+        };
+        let (whole, info) = grow_master_tree(&[
+            trace("main;a;x", 0),
+            trace("main;b", 10),
+            trace("main;a", 20),
+        ]);
+        let (alone, _) = grow_master_tree(&[trace("a;x", 0), trace("a", 20)]);
+        let options = MeshOptions {
+            num_graphs: 2,
+            ..MeshOptions::new_3d(&info)
+        };
+        let progress = JobProgress::default();
+        let zoom = ["main".to_string(), "a".to_string()];
+        let zoomed = get_mesh_from_tree(&whole, &zoom, &options, None, &progress).unwrap();
+        let expected = get_mesh_from_tree(&alone, &[], &options, None, &progress).unwrap();
+        assert!(!zoomed.verts.is_empty());
+        assert_eq!(zoomed.verts, expected.verts);
+        assert_eq!(zoomed.colors, expected.colors);
+        let missing = ["main".to_string(), "x".to_string()];
+        assert!(whole.zoomed(&missing).is_none());
+        let nothing = get_mesh_from_tree(&whole, &missing, &options, None, &progress).unwrap();
+        assert!(nothing.verts.is_empty());
     }
 }
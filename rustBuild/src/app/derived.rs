@@ -0,0 +1,337 @@
+use crate::app::diff::diverging_color;
+use crate::app::tree::*;
+/**  Derived
+ *   A small expression language for metrics computed from other metrics, such as
+ *       value / dur        misses / instructions * 100        "cycles:u" / (dur + 1)
+ *   Names are resolved against the loaded file when parsed, names that are not plain
+ *   identifiers can be written in double quotes. Dividing by zero gives zero
+ *   Graphs of an expression keep their bars sized by duration and color them by its value
+*/
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+enum Term {
+    Number(f64),
+    Duration,
+    Value,
+    Metric(usize),
+    Negate(Box<Term>),
+    Binary(Box<Term>, char, Box<Term>),
+}
+///a parsed expression, along with the text it came from
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub text: String,
+    term: Term,
+}
+///why an expression could not be parsed, column counts characters from 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionError {
+    pub column: usize,
+    pub message: String,
+}
+impl std::fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+impl std::error::Error for ExpressionError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() || c == '.' {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            let number = number.parse().map_err(|_| ExpressionError {
+                column: start + 1,
+                message: format!("`{}` is not a number", number),
+            })?;
+            tokens.push((start, Token::Number(number)));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((start, Token::Name(chars[start..i].iter().collect())));
+        } else if c == '"' {
+            let Some(length) = chars[i + 1..].iter().position(|c| *c == '"') else {
+                return Err(ExpressionError {
+                    column: start + 1,
+                    message: "the quoted name is never closed".to_string(),
+                });
+            };
+            tokens.push((
+                start,
+                Token::Name(chars[i + 1..i + 1 + length].iter().collect()),
+            ));
+            i += length + 2;
+        } else if "+-*/()".contains(c) {
+            tokens.push((start, Token::Symbol(c)));
+            i += 1;
+        } else {
+            return Err(ExpressionError {
+                column: start + 1,
+                message: format!("unexpected `{}`", c),
+            });
+        }
+    }
+    Ok(tokens)
+}
+///recursive descent over the tokens, `*` and `/` bind tighter than `+` and `-`
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    metrics: &'a [String],
+    end: usize,
+}
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|t| &t.1)
+    }
+    fn column(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |t| t.0) + 1
+    }
+    fn error(&self, message: &str) -> ExpressionError {
+        ExpressionError {
+            column: self.column(),
+            message: message.to_string(),
+        }
+    }
+    fn sum(&mut self) -> Result<Term, ExpressionError> {
+        let mut term = self.product()?;
+        while let Some(Token::Symbol(op @ ('+' | '-'))) = self.peek() {
+            let op = *op;
+            self.next += 1;
+            term = Term::Binary(Box::new(term), op, Box::new(self.product()?));
+        }
+        Ok(term)
+    }
+    fn product(&mut self) -> Result<Term, ExpressionError> {
+        let mut term = self.unary()?;
+        while let Some(Token::Symbol(op @ ('*' | '/'))) = self.peek() {
+            let op = *op;
+            self.next += 1;
+            term = Term::Binary(Box::new(term), op, Box::new(self.unary()?));
+        }
+        Ok(term)
+    }
+    fn unary(&mut self) -> Result<Term, ExpressionError> {
+        if self.peek() == Some(&Token::Symbol('-')) {
+            self.next += 1;
+            return Ok(Term::Negate(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+    fn atom(&mut self) -> Result<Term, ExpressionError> {
+        let term = match self.peek().cloned() {
+            Some(Token::Number(number)) => Term::Number(number),
+            Some(Token::Name(name)) => match name.as_str() {
+                "dur" | "duration" => Term::Duration,
+                "value" => Term::Value,
+                _ => match self.metrics.iter().position(|m| *m == name) {
+                    Some(index) => Term::Metric(index),
+                    None => return Err(self.error(&format!("no metric is named `{}`", name))),
+                },
+            },
+            Some(Token::Symbol('(')) => {
+                self.next += 1;
+                let term = self.sum()?;
+                if self.peek() != Some(&Token::Symbol(')')) {
+                    return Err(self.error("expected `)`"));
+                }
+                term
+            }
+            Some(_) => return Err(self.error("expected a number or a metric name")),
+            None => return Err(self.error("the expression ends early")),
+        };
+        self.next += 1;
+        Ok(term)
+    }
+}
+impl Expression {
+    ///parses an expression, `metrics` are the names from `TreeInfo.metrics`
+    pub fn parse(text: &str, metrics: &[String]) -> Result<Expression, ExpressionError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            next: 0,
+            metrics,
+            end: text.chars().count(),
+        };
+        let term = parser.sum()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("expected an operator"));
+        }
+        Ok(Expression {
+            text: text.trim().to_string(),
+            term,
+        })
+    }
+    ///evaluates the expression over one node's values, never negative or NaN
+    pub fn evaluate(&self, values: &TraceValues) -> f64 {
+        let result = evaluate(&self.term, values);
+        if result.is_finite() {
+            result.max(0.0)
+        } else {
+            0.0
+        }
+    }
+}
+///the color a derived value is drawn in, from grey at zero to red at the largest value drawn
+pub fn derived_color(value: f64, largest: f64) -> [f32; 4] {
+    if largest <= 0.0 {
+        return diverging_color(0.0);
+    }
+    diverging_color(value / largest)
+}
+///colors every node of the inspector's flamegraph by the expression's value over it
+pub fn shade_tree(tree: &mut Tree, expression: &Expression) {
+    fn largest(node: &Node, expression: &Expression) -> f64 {
+        node.children
+            .iter()
+            .map(|child| largest(child, expression))
+            .fold(expression.evaluate(&node.values), f64::max)
+    }
+    fn shade(node: &mut Node, expression: &Expression, largest: f64) {
+        if node.color.is_some() {
+            node.color = Some(derived_color(expression.evaluate(&node.values), largest));
+        }
+        for child in &mut node.children {
+            shade(child, expression, largest);
+        }
+    }
+    //the root stands for the whole slice, it is not drawn
+    let most = tree
+        .root
+        .children
+        .iter()
+        .map(|child| largest(child, expression))
+        .fold(0.0, f64::max);
+    for child in &mut tree.root.children {
+        shade(child, expression, most);
+    }
+}
+fn evaluate(term: &Term, values: &TraceValues) -> f64 {
+    match term {
+        Term::Number(number) => *number,
+        Term::Duration => values.dur as f64,
+        Term::Value => values.value as f64,
+        Term::Metric(index) => values.metric(*index) as f64,
+        Term::Negate(term) => -evaluate(term, values),
+        Term::Binary(left, op, right) => {
+            let (left, right) = (evaluate(left, values), evaluate(right, values));
+            match op {
+                '+' => left + right,
+                '-' => left - right,
+                '*' => left * right,
+                _ if right == 0.0 => 0.0,
+                _ => left / right,
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics() -> Vec<String> {
+        vec![
+            "cycles:u".to_string(),
+            "misses".to_string(),
+            "µs".to_string(),
+        ]
+    }
+    ///the expression over dur 4, value 10 and the metrics 6, 3 and 0
+    fn eval(text: &str) -> f64 {
+        let values = TraceValues {
+            dur: 4,
+            value: 10,
+            metrics: vec![6, 3, 0],
+            ..trace_zero()
+        };
+        Expression::parse(text, &metrics())
+            .unwrap()
+            .evaluate(&values)
+    }
+    ///the column and message an expression fails with
+    fn error(text: &str) -> (usize, String) {
+        let error = Expression::parse(text, &metrics()).unwrap_err();
+        (error.column, error.message)
+    }
+
+    #[test]
+    fn products_bind_tighter_than_sums() {
+        assert_eq!(eval("2 + 3 * 4"), 14.0);
+        assert_eq!(eval("(2 + 3) * 4"), 20.0);
+        assert_eq!(eval("value - dur - 1"), 5.0);
+        assert_eq!(eval("value / dur / 2"), 1.25);
+        assert_eq!(eval("value - misses * 2 + dur / 4"), 5.0);
+    }
+    #[test]
+    fn unary_minus() {
+        assert_eq!(eval("-2 * -3"), 6.0);
+        assert_eq!(eval("value - -dur"), 14.0);
+        assert_eq!(eval("--value"), 10.0);
+        assert_eq!(eval("-(dur - value)"), 6.0);
+        //results below zero are shown as zero
+        assert_eq!(eval("-value"), 0.0);
+    }
+    #[test]
+    fn quoted_names() {
+        assert_eq!(eval("\"cycles:u\" / (dur + 2)"), 1.0);
+        assert_eq!(eval("\"misses\" + misses"), 6.0);
+        assert_eq!(eval("\"µs\" + 1"), 1.0);
+        assert_eq!(
+            Expression::parse(" value ", &metrics()).unwrap().text,
+            "value"
+        );
+    }
+    #[test]
+    fn dividing_by_zero_gives_zero() {
+        assert_eq!(eval("value / (dur - duration)"), 0.0);
+        assert_eq!(eval("1 + value / µs"), 1.0);
+        assert_eq!(eval("misses / 0 * 5 + 2"), 2.0);
+    }
+    #[test]
+    fn errors_point_at_their_column() {
+        assert_eq!(
+            error("value +"),
+            (8, "the expression ends early".to_string())
+        );
+        assert_eq!(error("value $ 2"), (7, "unexpected `$`".to_string()));
+        assert_eq!(
+            error("2 * missing"),
+            (5, "no metric is named `missing`".to_string())
+        );
+        assert_eq!(error("(value"), (7, "expected `)`".to_string()));
+        assert_eq!(
+            error("value value"),
+            (7, "expected an operator".to_string())
+        );
+        assert_eq!(error("1..2"), (1, "`1..2` is not a number".to_string()));
+        assert_eq!(
+            error("dur * \"open"),
+            (7, "the quoted name is never closed".to_string())
+        );
+        assert_eq!(
+            error("* 2"),
+            (1, "expected a number or a metric name".to_string())
+        );
+        //columns count characters, not bytes
+        assert_eq!(error("µs + nope").0, 6);
+        assert_eq!(error("").0, 1);
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+
+use crate::app::data::{DataChoices, MeshOptions};
+use crate::app::tree::*;
+/**  Diff
+ *   Compares the loaded profile against a baseline, such as the same benchmark on an older build
+ *   The profiles are matched by call path, each node is colored by how much its share of the
+ *   metric grew (red) or shrank (blue), so a longer run does not paint everything red
+ *   Two slices of one profile are compared the same way in the inspector, and the trend
+ *   scheme colors each node by whether its share rises or falls across the time slices
+*/
+///the color for a change between -1 (all gone) and 1 (all new), grey when nothing changed
+pub fn diverging_color(delta: f64) -> [f32; 4] {
+    const NEUTRAL: [f32; 3] = [0.85, 0.85, 0.85];
+    const GREW: [f32; 3] = [0.9, 0.15, 0.1];
+    const SHRANK: [f32; 3] = [0.1, 0.35, 0.9];
+    let delta = delta.clamp(-1.0, 1.0) as f32;
+    let towards = if delta > 0.0 { GREW } else { SHRANK };
+    let t = delta.abs();
+    [
+        NEUTRAL[0] + (towards[0] - NEUTRAL[0]) * t,
+        NEUTRAL[1] + (towards[1] - NEUTRAL[1]) * t,
+        NEUTRAL[2] + (towards[2] - NEUTRAL[2]) * t,
+        1.0,
+    ]
+}
+///the change between two shares of a total, relative to the larger of them
+pub fn share_delta(before: f64, after: f64) -> f64 {
+    let larger = before.max(after);
+    if larger <= 0.0 {
+        return 0.0;
+    }
+    (after - before) / larger
+}
+///gives every node the baseline's total for the same call path, returns how many call paths
+///are only in the baseline, those can not be drawn in this profile's shape
+pub fn merge_baseline(tree: &mut MasterTree, baseline: &MasterTree) -> usize {
+    merge_node(&mut tree.root, Some(&baseline.root))
+}
+fn merge_node(node: &mut MasterNode, baseline: Option<&MasterNode>) -> usize {
+    node.baseline = baseline.map(|b| b.total());
+    let by_name: HashMap<&str, &MasterNode> = baseline
+        .map(|b| b.children.iter().map(|c| (c.name.as_str(), c)).collect())
+        .unwrap_or_default();
+    let mut matched = 0;
+    let mut missing = 0;
+    for child in &mut node.children {
+        let found = by_name.get(child.name.as_str()).copied();
+        matched += found.is_some() as usize;
+        missing += merge_node(child, found);
+    }
+    if matched < by_name.len() {
+        for gone in by_name.values() {
+            if !node.children.iter().any(|c| c.name == gone.name) {
+                missing += count_paths(gone);
+            }
+        }
+    }
+    missing
+}
+fn count_paths(node: &MasterNode) -> usize {
+    1 + node.children.iter().map(count_paths).sum::<usize>()
+}
+///forgets the baseline, the tree is colored by its scheme again separately
+pub fn clear_baseline(tree: &mut MasterTree) {
+    fn clear(node: &mut MasterNode) {
+        node.baseline = None;
+        for child in &mut node.children {
+            clear(child);
+        }
+    }
+    clear(&mut tree.root);
+}
+///colors every node by the change in its share of `metric` against the merged baseline
+pub fn diff_colors(tree: &mut MasterTree, metric: &DataChoices) {
+    let totals = (
+        tree.root.baseline.as_ref().map_or(0.0, |b| metric.of(b)),
+        metric.of(&tree.root.total()),
+    );
+    for child in &mut tree.root.children {
+        color_node(child, metric, totals);
+    }
+}
+fn color_node(node: &mut MasterNode, metric: &DataChoices, totals: (f64, f64)) {
+    let before = node
+        .baseline
+        .as_ref()
+        .map_or(0.0, |b| share(metric.of(b), totals.0));
+    let after = share(metric.of(&node.total()), totals.1);
+    node.color = Some(diverging_color(share_delta(before, after)));
+    for child in &mut node.children {
+        color_node(child, metric, totals);
+    }
+}
+fn share(amount: f64, total: f64) -> f64 {
+    if total > 0.0 {
+        amount / total
+    } else {
+        0.0
+    }
+}
+///colors the nodes of a flamegraph by how their share of `metric` changed from `before`,
+///such as an earlier time slice of the same profile
+pub fn diff_tree_colors(tree: &mut Tree, before: &Tree, metric: &DataChoices) {
+    let totals = (metric.of(&before.root.values), metric.of(&tree.root.values));
+    color_tree_children(&mut tree.root, Some(&before.root), metric, totals);
+}
+fn color_tree_children(
+    node: &mut Node,
+    before: Option<&Node>,
+    metric: &DataChoices,
+    totals: (f64, f64),
+) {
+    let by_name: HashMap<&str, &Node> = before
+        .map(|b| b.children.iter().map(|c| (c.name.as_str(), c)).collect())
+        .unwrap_or_default();
+    for child in &mut node.children {
+        let found = by_name.get(child.name.as_str()).copied();
+        let share_before = found.map_or(0.0, |b| share(metric.of(&b.values), totals.0));
+        let share_after = share(metric.of(&child.values), totals.1);
+        child.color = Some(diverging_color(share_delta(share_before, share_after)));
+        color_tree_children(child, found, metric, totals);
+    }
+}
+///colors every node by the trend of its share of the metric across the time slices of
+///`options`, red when it rises from the first slice to the last and blue when it falls
+pub fn trend_colors(tree: &mut MasterTree, options: &MeshOptions) {
+    let (range, attribution) = (options.time_range.clone(), options.attribution);
+    let Some(totals) = tree
+        .root
+        .time_overlaps(options.num_graphs, range, attribution)
+    else {
+        return;
+    };
+    let totals: Vec<f64> = totals.iter().map(|t| options.data_metric.of(t)).collect();
+    for child in &mut tree.root.children {
+        trend_node(child, options, &totals);
+    }
+}
+fn trend_node(node: &mut MasterNode, options: &MeshOptions, totals: &[f64]) {
+    let range = options.time_range.clone();
+    let delta = match node.time_overlaps(options.num_graphs, range, options.attribution) {
+        Some(overlaps) => {
+            let shares: Vec<f64> = overlaps
+                .iter()
+                .zip(totals)
+                .map(|(overlap, total)| share(options.data_metric.of(overlap), *total))
+                .collect();
+            let (first, last) = fitted_ends(&shares);
+            share_delta(first, last)
+        }
+        None => 0.0,
+    };
+    node.color = Some(diverging_color(delta));
+    for child in &mut node.children {
+        trend_node(child, options, totals);
+    }
+}
+///the least squares line through the shares, at the first and the last slice
+fn fitted_ends(shares: &[f64]) -> (f64, f64) {
+    if shares.len() < 2 {
+        return (0.0, 0.0);
+    }
+    let n = shares.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = shares.iter().sum::<f64>() / n;
+    let mut covariance = 0.0;
+    let mut variance = 0.0;
+    for (x, y) in shares.iter().enumerate() {
+        let dx = x as f64 - mean_x;
+        covariance += dx * (y - mean_y);
+        variance += dx * dx;
+    }
+    let slope = covariance / variance;
+    (mean_y - slope * mean_x, mean_y + slope * mean_x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::data::{Attribution, MeshOptions};
+
+    const NEUTRAL: [f32; 4] = [0.85, 0.85, 0.85, 1.0];
+    ///a profile of one call of each path worth its value, one after the other
+    fn profile(calls: &[(&str, u64)]) -> MasterTree {
+        let traces: Vec<Trace> = calls
+            .iter()
+            .enumerate()
+            .map(|(i, (path, value))| Trace {
+                start: i as u64 * 10,
+                value: *value,
+                ..test_trace(path)
+            })
+            .collect();
+        grow_master_tree(&traces).0
+    }
+    fn master_node<'a>(tree: &'a MasterTree, path: &str) -> &'a MasterNode {
+        let mut node = &tree.root;
+        for name in path.split(';') {
+            node = node.children.iter().find(|c| c.name == name).unwrap();
+        }
+        node
+    }
+    fn grew(color: [f32; 4]) -> bool {
+        color[0] > color[2]
+    }
+    fn shrank(color: [f32; 4]) -> bool {
+        color[2] > color[0]
+    }
+
+    #[test]
+    fn call_paths_on_one_side_only() {
+        let mut tree = profile(&[("main;a", 1), ("main;b", 1)]);
+        let baseline = profile(&[("main;a", 2), ("main;c;d", 1)]);
+        //c and d can not be drawn, b is drawn without a baseline
+        assert_eq!(merge_baseline(&mut tree, &baseline), 2);
+        assert_eq!(
+            master_node(&tree, "main;a")
+                .baseline
+                .as_ref()
+                .unwrap()
+                .value,
+            2
+        );
+        assert!(master_node(&tree, "main;b").baseline.is_none());
+        diff_colors(&mut tree, &DataChoices::Value);
+        assert_eq!(
+            master_node(&tree, "main;b").color,
+            Some(diverging_color(1.0))
+        );
+        clear_baseline(&mut tree);
+        assert!(master_node(&tree, "main;a").baseline.is_none());
+    }
+    #[test]
+    fn equal_shares_are_neutral_and_changes_are_signed() {
+        //twice as long a run, but only b's share changed
+        let baseline = profile(&[("main;a", 1), ("main;b", 1), ("main;c", 2)]);
+        let mut tree = profile(&[("main;a", 2), ("main;b", 4), ("main;c", 2)]);
+        merge_baseline(&mut tree, &baseline);
+        diff_colors(&mut tree, &DataChoices::Value);
+        let color = |path| master_node(&tree, path).color.unwrap();
+        assert_eq!(color("main"), NEUTRAL);
+        assert_eq!(color("main;a"), NEUTRAL);
+        assert!(grew(color("main;b")));
+        assert!(shrank(color("main;c")));
+    }
+    #[test]
+    fn slices_are_compared_by_share() {
+        let master = profile(&[("main;a", 1), ("main;b", 1), ("main;a", 2), ("main;c", 2)]);
+        let slice = |range| build_time_tree(&master, range, Attribution::Proportional);
+        let before = slice(0..20);
+        let mut tree = slice(20..40);
+        diff_tree_colors(&mut tree, &before, &DataChoices::Value);
+        let main = &tree.root.children[0];
+        assert_eq!(main.color, Some(NEUTRAL));
+        let color = |name| {
+            let node = main.children.iter().find(|c| c.name == name).unwrap();
+            node.color.unwrap()
+        };
+        assert_eq!(color("a"), NEUTRAL);
+        //c is only in the later slice
+        assert_eq!(color("c"), diverging_color(1.0));
+        assert!(grew(color("c")));
+        assert!(main.children.iter().all(|c| c.name != "b"));
+    }
+    #[test]
+    fn fitted_ends_follow_the_line() {
+        let (first, last) = fitted_ends(&[0.1, 0.2, 0.3, 0.4]);
+        assert!((first - 0.1).abs() < 1e-9 && (last - 0.4).abs() < 1e-9);
+        assert_eq!(fitted_ends(&[0.5, 0.5, 0.5]), (0.5, 0.5));
+        //one slice has no trend, and must not divide by zero
+        assert_eq!(fitted_ends(&[0.5]), (0.0, 0.0));
+        assert_eq!(fitted_ends(&[]), (0.0, 0.0));
+    }
+    #[test]
+    fn trends_color_by_the_direction_of_the_share() {
+        //a's share rises by the same step every slice while b's falls, main stays whole
+        let traces: Vec<Trace> = (0..4)
+            .flat_map(|k| {
+                [
+                    Trace {
+                        start: k * 20,
+                        value: k + 1,
+                        ..test_trace("main;a")
+                    },
+                    Trace {
+                        start: k * 20 + 10,
+                        value: 4 - k,
+                        ..test_trace("main;b")
+                    },
+                ]
+            })
+            .collect();
+        let (mut tree, info) = grow_master_tree(&traces);
+        let options = MeshOptions {
+            time_range: 0..80,
+            num_graphs: 4,
+            data_metric: DataChoices::Value,
+            attribution: Attribution::Start,
+            ..MeshOptions::new_3d(&info)
+        };
+        trend_colors(&mut tree, &options);
+        let color = |tree: &MasterTree, path| master_node(tree, path).color.unwrap();
+        assert_eq!(color(&tree, "main"), NEUTRAL);
+        //the line runs from a fifth to four fifths, three quarters of the way to growing
+        assert_eq!(color(&tree, "main;a"), diverging_color(0.75));
+        assert!(grew(color(&tree, "main;a")));
+        assert!(shrank(color(&tree, "main;b")));
+
+        let one_slice = MeshOptions {
+            num_graphs: 1,
+            ..options
+        };
+        trend_colors(&mut tree, &one_slice);
+        for path in ["main", "main;a", "main;b"] {
+            assert_eq!(color(&tree, path), NEUTRAL);
+        }
+    }
+}
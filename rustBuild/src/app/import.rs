@@ -0,0 +1,378 @@
+mod cache;
+mod chrome;
+mod folded;
+mod perf;
+mod pprof;
+mod speedscope;
+
+use crate::app::tree::*;
+pub use cache::{decode_cache, encode_cache, is_cache, CacheSource, CACHE_EXTENSION};
+#[cfg(any(test, target_arch = "wasm32"))]
+pub use cache::{decode_tree, encode_tree};
+use chrome::*;
+use folded::*;
+use perf::*;
+use pprof::*;
+use speedscope::{is_speedscope, load_speedscope};
+pub use speedscope::{master_to_speedscope, tree_to_speedscope};
+/**  Import
+ *   Picks the right parser for a trace file and grows the master tree from it
+*/
+///extensions offered by the file dialog
+pub const TRACE_EXTENSIONS: &[&str] = &[
+    "json",
+    "folded",
+    "collapsed",
+    "txt",
+    "pprof",
+    "pb",
+    "gz",
+    "perf",
+    "script",
+    CACHE_EXTENSION,
+];
+///the file formats that can be loaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceFormat {
+    Native,
+    Chrome,
+    Folded,
+    Pprof,
+    PerfScript,
+    Speedscope,
+    Cache,
+}
+///choices about how a file is read that are not stored in the file itself
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    ///the pprof sample type used as each trace's value, the profile's default when None
+    pub sample_type: Option<String>,
+}
+///guesses the format of a file from its contents
+pub fn detect_format(name: &str, input: &[u8]) -> TraceFormat {
+    if is_cache(input) {
+        TraceFormat::Cache
+    } else if is_pprof(name, input) {
+        TraceFormat::Pprof
+    } else if is_perf_script(name, input) {
+        TraceFormat::PerfScript
+    } else if is_folded(name, input) {
+        TraceFormat::Folded
+    } else if is_speedscope(input) {
+        TraceFormat::Speedscope
+    } else if is_chrome_trace(input) {
+        TraceFormat::Chrome
+    } else {
+        TraceFormat::Native
+    }
+}
+///parses a trace file held in memory in any of the supported formats
+pub fn import_tree(
+    name: &str,
+    input: &[u8],
+    options: &ImportOptions,
+) -> Result<(MasterTree, TreeInfo), TraceError> {
+    let traces = match detect_format(name, input) {
+        TraceFormat::Native => return load_data(input),
+        TraceFormat::Cache => return decode_cache(input),
+        TraceFormat::Chrome => load_chrome(input),
+        TraceFormat::Folded => load_folded(input),
+        TraceFormat::Pprof => {
+            let (traces, sample_types) = load_pprof(input, &options.sample_type)?;
+            let (tree, mut info) = grow_master_tree(&traces);
+            info.sample_types = sample_types;
+            return Ok((tree, info));
+        }
+        TraceFormat::PerfScript => load_perf_script(input),
+        TraceFormat::Speedscope => load_speedscope(input),
+    }?;
+    Ok(grow_master_tree(&traces))
+}
+///how much of a file is looked at to tell its format
+pub const HEAD_LEN: usize = 4096;
+///imports a file from disk, native traces are parsed as they are read so a file larger than
+///memory can be loaded, the other formats are read whole first
+#[cfg(not(target_arch = "wasm32"))]
+pub fn import_file(
+    path: &std::path::Path,
+    options: &ImportOptions,
+) -> Result<(MasterTree, TreeInfo), TraceError> {
+    use std::io::{Read, Seek};
+    let name = path.display().to_string();
+    let mut file = std::fs::File::open(path)?;
+    let mut head = vec![];
+    (&mut file).take(HEAD_LEN as u64).read_to_end(&mut head)?;
+    if detect_format(&name, &head) == TraceFormat::Native {
+        file.rewind()?;
+        return load_data_from_reader(std::io::BufReader::new(file));
+    }
+    let mut input = head;
+    file.read_to_end(&mut input)?;
+    import_tree(&name, &input, options)
+}
+///imports a file through a processed copy kept next to it, a stale or broken copy is ignored
+///and the file is imported again
+#[cfg(not(target_arch = "wasm32"))]
+pub fn import_file_cached(
+    path: &std::path::Path,
+    options: &ImportOptions,
+) -> Result<(MasterTree, TreeInfo), TraceError> {
+    let stamp = source_stamp(path, options)?;
+    let cache_path = format!("{}.{}", path.display(), CACHE_EXTENSION);
+    let mut hash = None;
+    let bytes = std::fs::read(&cache_path).unwrap_or_default();
+    if let Ok(cached) = cache::cache_source(&bytes) {
+        //a file that was touched or copied but not changed still matches by its contents
+        let stamped = stamp == Some(cached.stamp);
+        if !stamped {
+            hash = Some(source_hash(path, options)?);
+        }
+        if stamped || hash == Some(cached.hash) {
+            if let Ok((tree, info)) = decode_cache(&bytes) {
+                if let (false, Some(stamp)) = (stamped, stamp) {
+                    //stamped again, so the next load does not hash the file
+                    let source = CacheSource { stamp, ..cached };
+                    let _ = std::fs::write(&cache_path, encode_cache(&tree, &info, source));
+                }
+                return Ok((tree, info));
+            }
+        }
+    }
+    let result = import_file(path, options);
+    if let Ok((tree, info)) = &result {
+        //the cache only saves time, a file that can't be hashed or a folder that can't be
+        //written to is not an error
+        if let Some(hash) = hash.or_else(|| source_hash(path, options).ok()) {
+            let source = CacheSource {
+                stamp: stamp.unwrap_or(0),
+                hash,
+            };
+            let _ = std::fs::write(&cache_path, encode_cache(tree, info, source));
+        }
+    }
+    result
+}
+///the cheap check of a cache, covering the file's length and modification time and the options
+///it was read with, None when the file has no modification time and only the hash can be checked
+#[cfg(not(target_arch = "wasm32"))]
+pub fn source_stamp(
+    path: &std::path::Path,
+    options: &ImportOptions,
+) -> Result<Option<u64>, TraceError> {
+    let metadata = std::fs::metadata(path)?;
+    let Ok(modified) = metadata.modified() else {
+        return Ok(None);
+    };
+    let nanos = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64);
+    let stamp = cache::fnv_start(options_seed(options));
+    let stamp = cache::fnv_extend(stamp, &metadata.len().to_le_bytes());
+    Ok(Some(cache::fnv_extend(stamp, &nanos.to_le_bytes())))
+}
+#[cfg(not(target_arch = "wasm32"))]
+fn options_seed(options: &ImportOptions) -> u64 {
+    cache::fnv_hash(options.sample_type.as_deref().unwrap_or("").as_bytes(), 0)
+}
+///the hash a cache falls back to, covering both the file and the options it was read with
+///the file is hashed a piece at a time, so it is never all in memory
+#[cfg(not(target_arch = "wasm32"))]
+pub fn source_hash(path: &std::path::Path, options: &ImportOptions) -> Result<u64, TraceError> {
+    use std::io::Read;
+    let mut hash = cache::fnv_start(options_seed(options));
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hash);
+        }
+        hash = cache::fnv_extend(hash, &buffer[..read]);
+    }
+}
+///orders names with the numbers in them compared by value, so snap2 comes before snap10
+pub fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    ///splits a name into runs of digits and runs of everything else
+    fn runs(name: &str) -> impl Iterator<Item = &str> + '_ {
+        let mut rest = name;
+        std::iter::from_fn(move || {
+            let digit = rest.chars().next()?.is_ascii_digit();
+            let end = rest
+                .find(|c: char| c.is_ascii_digit() != digit)
+                .unwrap_or(rest.len());
+            let (run, tail) = rest.split_at(end);
+            rest = tail;
+            Some(run)
+        })
+    }
+    let (mut a_runs, mut b_runs) = (runs(a), runs(b));
+    loop {
+        let (x, y) = match (a_runs.next(), b_runs.next()) {
+            //names that only differ in leading zeros still get an order
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => (x, y),
+        };
+        let numbers = x.as_bytes()[0].is_ascii_digit() && y.as_bytes()[0].is_ascii_digit();
+        let order = if numbers {
+            let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+            x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+        } else {
+            x.cmp(y)
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+///parses several folded files at once, in the order given, one time slice per file
+pub fn import_snapshots(inputs: &[&[u8]]) -> Result<(MasterTree, TreeInfo), TraceError> {
+    Ok(grow_master_tree(&load_folded_sequence(inputs)?))
+}
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    ///a folded trace in a folder of its own, removed when dropped
+    struct TempTrace {
+        dir: PathBuf,
+        path: PathBuf,
+    }
+    impl TempTrace {
+        fn new(test: &str, input: &str) -> TempTrace {
+            let dir =
+                std::env::temp_dir().join(format!("metaflame-{}-{}", test, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("trace.folded");
+            std::fs::write(&path, input).unwrap();
+            TempTrace { dir, path }
+        }
+        fn cache_path(&self) -> String {
+            format!("{}.{}", self.path.display(), CACHE_EXTENSION)
+        }
+        ///replaces the cache with one of `input`, made from `source`
+        fn fake_cache(&self, input: &str, source: CacheSource) {
+            let (tree, info) =
+                import_tree("fake.folded", input.as_bytes(), &Default::default()).unwrap();
+            std::fs::write(self.cache_path(), encode_cache(&tree, &info, source)).unwrap();
+        }
+    }
+    impl Drop for TempTrace {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+    fn total(loaded: &(MasterTree, TreeInfo)) -> u64 {
+        loaded
+            .0
+            .root
+            .children
+            .iter()
+            .flat_map(|c| &c.values)
+            .map(|v| v.value)
+            .sum()
+    }
+
+    #[test]
+    fn snapshot_names_sort_by_number() {
+        let mut names = vec!["snap10", "snap2", "snap1", "base", "snap02b", "snap002"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["base", "snap1", "snap002", "snap2", "snap02b", "snap10"]
+        );
+    }
+    #[test]
+    fn cache_is_written_and_reused() {
+        let trace = TempTrace::new("reused", "main;f 10\n");
+        let options = ImportOptions::default();
+        assert_eq!(
+            total(&import_file_cached(&trace.path, &options).unwrap()),
+            10
+        );
+        let cache = std::fs::read(trace.cache_path()).unwrap();
+        let source = CacheSource {
+            stamp: source_stamp(&trace.path, &options).unwrap().unwrap(),
+            hash: source_hash(&trace.path, &options).unwrap(),
+        };
+        assert_eq!(cache::cache_source(&cache).unwrap(), source);
+        assert_eq!(
+            total(&import_file_cached(&trace.path, &options).unwrap()),
+            10
+        );
+    }
+    #[test]
+    fn stale_or_broken_caches_are_imported_again() {
+        let trace = TempTrace::new("stale", "main;f 10\n");
+        let options = ImportOptions::default();
+        import_file_cached(&trace.path, &options).unwrap();
+        let good = std::fs::read(trace.cache_path()).unwrap();
+
+        let mut version = good.clone();
+        version[4] += 1;
+        std::fs::write(trace.cache_path(), version).unwrap();
+        assert_eq!(
+            total(&import_file_cached(&trace.path, &options).unwrap()),
+            10
+        );
+        assert_eq!(std::fs::read(trace.cache_path()).unwrap(), good);
+
+        let mut corrupt = good.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        std::fs::write(trace.cache_path(), corrupt).unwrap();
+        assert_eq!(
+            total(&import_file_cached(&trace.path, &options).unwrap()),
+            10
+        );
+        assert_eq!(std::fs::read(trace.cache_path()).unwrap(), good);
+
+        //a changed trace no longer matches what its cache was made from
+        std::fs::write(&trace.path, "main;f 250\n").unwrap();
+        assert_eq!(
+            total(&import_file_cached(&trace.path, &options).unwrap()),
+            250
+        );
+    }
+    #[test]
+    fn caches_are_checked_by_stamp_before_contents() {
+        let trace = TempTrace::new("stamped", "main;f 10\n");
+        let options = ImportOptions::default();
+        let stamp = source_stamp(&trace.path, &options).unwrap().unwrap();
+        let hash = source_hash(&trace.path, &options).unwrap();
+        //a matching stamp is trusted without hashing, so a cache with any hash is used
+        trace.fake_cache("main;f 99\n", CacheSource { stamp, hash: 0 });
+        assert_eq!(
+            total(&import_file_cached(&trace.path, &options).unwrap()),
+            99
+        );
+        //a touched file is matched by its contents, and its cache stamped again
+        let touched = CacheSource {
+            stamp: stamp ^ 1,
+            hash,
+        };
+        trace.fake_cache("main;f 98\n", touched);
+        assert_eq!(
+            total(&import_file_cached(&trace.path, &options).unwrap()),
+            98
+        );
+        let restamped = cache::cache_source(&std::fs::read(trace.cache_path()).unwrap()).unwrap();
+        assert_eq!(restamped, CacheSource { stamp, hash });
+        //a stamp and contents that both differ are a changed file
+        trace.fake_cache("main;f 97\n", CacheSource { stamp: 0, hash: 0 });
+        assert_eq!(
+            total(&import_file_cached(&trace.path, &options).unwrap()),
+            10
+        );
+        //other options are another source
+        let counts = ImportOptions {
+            sample_type: Some("samples (count)".to_string()),
+        };
+        assert_eq!(
+            total(&import_file_cached(&trace.path, &counts).unwrap()),
+            10
+        );
+    }
+}
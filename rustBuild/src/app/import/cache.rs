@@ -0,0 +1,483 @@
+use crate::app::tree::*;
+/**  Cache
+ *   A compact binary copy of a processed master tree, so big profiles skip parsing on later loads
+ *       magic, version, source stamp, source hash, checksum, then the tree info, its threads and
+ *       every node depth first
+ *   Anything that does not match exactly is rejected and the source file is imported again
+*/
+const MAGIC: &[u8; 4] = b"MFTC";
+///bump whenever the layout of the cache changes
+const VERSION: u32 = 7;
+const HEADER_LEN: usize = 4 + 4 + 8 + 8 + 8;
+///extension added to a trace file's path for its cache, caches are only written when asked for
+pub const CACHE_EXTENSION: &str = "mfcache";
+
+///64 bit FNV-1a, stable across builds unlike the std hasher
+pub fn fnv_hash(bytes: &[u8], seed: u64) -> u64 {
+    fnv_extend(fnv_start(seed), bytes)
+}
+pub fn fnv_start(seed: u64) -> u64 {
+    seed ^ 0xcbf2_9ce4_8422_2325
+}
+///continues a hash with more bytes, so a file can be hashed a piece at a time
+pub fn fnv_extend(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+///what a cache was made from, it is only used while its source file still matches
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CacheSource {
+    ///the file's length and modification time and the options it was read with, cheap to check
+    pub stamp: u64,
+    ///the file's contents and the options, checked when the stamp no longer matches
+    pub hash: u64,
+}
+///true if the input starts with the cache magic
+pub fn is_cache(input: &[u8]) -> bool {
+    input.starts_with(MAGIC)
+}
+
+fn write_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+fn write_u64(out: &mut Vec<u8>, n: u64) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+fn write_string(out: &mut Vec<u8>, string: &str) {
+    write_u32(out, string.len() as u32);
+    out.extend_from_slice(string.as_bytes());
+}
+fn write_node(out: &mut Vec<u8>, node: &MasterNode) {
+    write_string(out, &node.name);
+    match node.color {
+        Some(color) => {
+            out.push(1);
+            for channel in color {
+                out.extend_from_slice(&channel.to_le_bytes());
+            }
+        }
+        None => out.push(0),
+    }
+    write_u64(out, node.values.len() as u64);
+    for value in &node.values {
+        write_u64(out, value.start);
+        write_u64(out, value.dur);
+        write_u64(out, value.value);
+        write_u64(out, value.thread as u64);
+        write_u32(out, value.metrics.len() as u32);
+        for metric in &value.metrics {
+            write_u64(out, *metric);
+        }
+    }
+    write_u32(out, node.children.len() as u32);
+    for child in &node.children {
+        write_node(out, child);
+    }
+}
+fn write_tree(out: &mut Vec<u8>, tree: &MasterTree) {
+    out.push(match tree.color_scheme {
+        ColorScheme::Rainbow => 0,
+        ColorScheme::Greyscale => 1,
+        ColorScheme::Flame => 2,
+        ColorScheme::Ice => 3,
+        ColorScheme::Trend => 4,
+    });
+    write_u32(out, tree.color_salt);
+    write_node(out, &tree.root);
+}
+///writes the tree and its info, `source` is the file it was imported from
+pub fn encode_cache(tree: &MasterTree, info: &TreeInfo, source: CacheSource) -> Vec<u8> {
+    let mut payload = vec![];
+    write_u64(&mut payload, info.max_depth as u64);
+    write_u64(&mut payload, info.num_threads as u64);
+    write_u64(&mut payload, info.start);
+    write_u64(&mut payload, info.end);
+    write_u64(&mut payload, info.nodes);
+    write_u32(&mut payload, info.metrics.len() as u32);
+    for metric in &info.metrics {
+        write_string(&mut payload, metric);
+    }
+    write_u32(&mut payload, info.sample_types.len() as u32);
+    for sample_type in &info.sample_types {
+        write_string(&mut payload, sample_type);
+    }
+    write_u32(&mut payload, info.threads.entries.len() as u32);
+    for thread in &info.threads.entries {
+        write_u64(&mut payload, thread.pid as u64);
+        write_u64(&mut payload, thread.tid as u64);
+        write_string(&mut payload, &thread.name);
+        write_u32(&mut payload, thread.metadata.len() as u32);
+        for (key, value) in &thread.metadata {
+            write_string(&mut payload, key);
+            write_string(&mut payload, value);
+        }
+    }
+    write_u32(&mut payload, info.threads.processes.len() as u32);
+    for process in &info.threads.processes {
+        write_string(&mut payload, &process.name);
+    }
+    write_tree(&mut payload, tree);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(MAGIC);
+    write_u32(&mut out, VERSION);
+    write_u64(&mut out, source.stamp);
+    write_u64(&mut out, source.hash);
+    write_u64(&mut out, fnv_hash(&payload, 0));
+    out.extend_from_slice(&payload);
+    out
+}
+
+///reads values off the front of a byte slice, every read fails once the slice runs out
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], TraceError> {
+        if self.bytes.len() < n {
+            return Err(TraceError::Decode("cache ends early".to_string()));
+        }
+        let (front, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(front)
+    }
+    fn u8(&mut self) -> Result<u8, TraceError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, TraceError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Result<u64, TraceError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    fn f32(&mut self) -> Result<f32, TraceError> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn usize(&mut self) -> Result<usize, TraceError> {
+        usize::try_from(self.u64()?).map_err(|_| {
+            TraceError::Decode("cache holds a count too large for this machine".to_string())
+        })
+    }
+    fn string(&mut self) -> Result<String, TraceError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| TraceError::Decode("cache holds a name that is not UTF-8".to_string()))
+    }
+    ///a u32 count followed by that many items, each at least `item_len` bytes long
+    fn list<T>(
+        &mut self,
+        item_len: usize,
+        read: fn(&mut Self) -> Result<T, TraceError>,
+    ) -> Result<Vec<T>, TraceError> {
+        let count = self.u32()? as usize;
+        //a corrupt count can not reserve more than the bytes left could hold
+        let mut items = Vec::with_capacity(count.min(self.bytes.len() / item_len));
+        for _ in 0..count {
+            items.push(read(self)?);
+        }
+        Ok(items)
+    }
+}
+fn read_thread(reader: &mut Reader<'_>) -> Result<ThreadEntry, TraceError> {
+    Ok(ThreadEntry {
+        pid: reader.usize()?,
+        tid: reader.usize()?,
+        process: 0,
+        name: reader.string()?,
+        metadata: reader
+            .list(8, |reader| Ok((reader.string()?, reader.string()?)))?
+            .into_iter()
+            .collect(),
+    })
+}
+///reads the nodes depth first with a stack of the ones still waiting for children, so a deep
+///tree can not overflow the call stack
+fn read_node(reader: &mut Reader<'_>) -> Result<MasterNode, TraceError> {
+    let mut waiting: Vec<(MasterNode, usize)> = vec![];
+    loop {
+        let (mut node, count) = read_node_head(reader)?;
+        if count > 0 {
+            waiting.push((node, count));
+            continue;
+        }
+        //a finished node may be the last child its parent waits for, and so on up the stack
+        loop {
+            let Some((parent, left)) = waiting.last_mut() else {
+                return Ok(node);
+            };
+            parent.children.push(node);
+            *left -= 1;
+            if *left > 0 {
+                break;
+            }
+            node = waiting.pop().unwrap().0;
+        }
+    }
+}
+///a node without its children, and how many children follow it
+fn read_node_head(reader: &mut Reader<'_>) -> Result<(MasterNode, usize), TraceError> {
+    let name = reader.string()?;
+    let color = match reader.u8()? {
+        0 => None,
+        _ => Some([reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?]),
+    };
+    let count = reader.usize()?;
+    //a corrupt count can not reserve more than the bytes left could hold
+    let mut values = Vec::with_capacity(count.min(reader.bytes.len() / 32));
+    for _ in 0..count {
+        values.push(TraceValues {
+            start: reader.u64()?,
+            dur: reader.u64()?,
+            value: reader.u64()?,
+            thread: reader.usize()?,
+            metrics: reader.list(8, Reader::u64)?,
+        });
+    }
+    let count = reader.u32()? as usize;
+    let node = MasterNode {
+        name,
+        values,
+        children: Vec::with_capacity(count.min(reader.bytes.len())),
+        color,
+        index: TimeIndex::default(),
+        baseline: None,
+    };
+    Ok((node, count))
+}
+///the file a cache was made from, checked before the rest of it is read
+#[cfg(any(test, not(target_arch = "wasm32")))]
+pub fn cache_source(input: &[u8]) -> Result<CacheSource, TraceError> {
+    read_header(&mut Reader { bytes: input })
+}
+fn read_header(reader: &mut Reader<'_>) -> Result<CacheSource, TraceError> {
+    if reader.take(4)? != MAGIC {
+        return Err(TraceError::Decode("not a cache file".to_string()));
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        return Err(TraceError::Decode(format!(
+            "cache version {} does not match version {}",
+            version, VERSION
+        )));
+    }
+    Ok(CacheSource {
+        stamp: reader.u64()?,
+        hash: reader.u64()?,
+    })
+}
+///reads a cache back, checking its version and its checksum
+pub fn decode_cache(input: &[u8]) -> Result<(MasterTree, TreeInfo), TraceError> {
+    let mut reader = Reader { bytes: input };
+    read_header(&mut reader)?;
+    let checksum = reader.u64()?;
+    if fnv_hash(reader.bytes, 0) != checksum {
+        return Err(TraceError::Decode(
+            "cache checksum does not match".to_string(),
+        ));
+    }
+    let mut info = TreeInfo {
+        max_depth: reader.usize()?,
+        num_threads: reader.usize()?,
+        start: reader.u64()?,
+        end: reader.u64()?,
+        nodes: reader.u64()?,
+        metrics: reader.list(4, Reader::string)?,
+        sample_types: reader.list(4, Reader::string)?,
+        threads: Default::default(),
+    };
+    //registering the threads again in order gives them the same indices
+    for thread in reader.list(24, read_thread)? {
+        let index = info.threads.register(thread.pid, thread.tid);
+        info.threads.entries[index].name = thread.name;
+        info.threads.entries[index].metadata = thread.metadata;
+    }
+    let process_names = reader.list(4, Reader::string)?;
+    if process_names.len() != info.threads.processes.len() {
+        return Err(TraceError::Decode(
+            "cache has names for a different number of processes".to_string(),
+        ));
+    }
+    for (process, name) in info.threads.processes.iter_mut().zip(process_names) {
+        process.name = name;
+    }
+    let tree = read_tree(&mut reader)?;
+    Ok((tree, info))
+}
+fn read_tree(reader: &mut Reader<'_>) -> Result<MasterTree, TraceError> {
+    let color_scheme = match reader.u8()? {
+        0 => ColorScheme::Rainbow,
+        1 => ColorScheme::Greyscale,
+        2 => ColorScheme::Flame,
+        3 => ColorScheme::Ice,
+        4 => ColorScheme::Trend,
+        scheme => {
+            return Err(TraceError::Decode(format!(
+                "cache has unknown color scheme {}",
+                scheme
+            )))
+        }
+    };
+    let color_salt = reader.u32()?;
+    //the time index is quick to rebuild, so it is not stored
+    let mut root = read_node(reader)?;
+    root.build_index();
+    if !reader.bytes.is_empty() {
+        return Err(TraceError::Decode(
+            "cache has data past the end of the tree".to_string(),
+        ));
+    }
+    Ok(MasterTree {
+        root,
+        color_scheme,
+        color_salt,
+    })
+}
+///just the tree, without a header or info, for handing a tree to a web worker
+#[cfg(any(test, target_arch = "wasm32"))]
+pub fn encode_tree(tree: &MasterTree) -> Vec<u8> {
+    let mut out = vec![];
+    write_tree(&mut out, tree);
+    out
+}
+#[cfg(any(test, target_arch = "wasm32"))]
+pub fn decode_tree(input: &[u8]) -> Result<MasterTree, TraceError> {
+    read_tree(&mut Reader { bytes: input })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::import::{import_tree, ImportOptions};
+
+    fn loaded() -> (MasterTree, TreeInfo) {
+        let input = b"main;parse 30\nmain;draw 10\nmain 5\n";
+        import_tree("a.folded", input, &ImportOptions::default()).unwrap()
+    }
+    ///a node's name with the (start, dur, value) of each of its events
+    type FlatNode = (String, Vec<(u64, u64, u64)>);
+    ///every node, depth first
+    fn flatten(node: &MasterNode, out: &mut Vec<FlatNode>) {
+        let values = node
+            .values
+            .iter()
+            .map(|v| (v.start, v.dur, v.value))
+            .collect();
+        out.push((node.name.clone(), values));
+        for child in &node.children {
+            flatten(child, out);
+        }
+    }
+    fn nodes(tree: &MasterTree) -> Vec<FlatNode> {
+        let mut out = vec![];
+        flatten(&tree.root, &mut out);
+        out
+    }
+
+    #[test]
+    fn cache_round_trips() {
+        let (mut tree, mut info) = loaded();
+        info.sample_types = vec![
+            "samples (count)".to_string(),
+            "cpu (nanoseconds)".to_string(),
+        ];
+        tree.color_scheme = ColorScheme::Flame;
+        tree.color_salt = 7;
+        tree.new_color_scheme();
+        let source = CacheSource {
+            stamp: 41,
+            hash: 42,
+        };
+        let bytes = encode_cache(&tree, &info, source);
+        assert_eq!(cache_source(&bytes).unwrap(), source);
+        let (read, read_info) = decode_cache(&bytes).unwrap();
+        assert_eq!(nodes(&read), nodes(&tree));
+        assert_eq!(read.root.children[0].color, tree.root.children[0].color);
+        assert_eq!(read.color_scheme, ColorScheme::Flame);
+        assert_eq!(read.color_salt, 7);
+        assert_eq!(
+            (
+                read_info.start,
+                read_info.end,
+                read_info.nodes,
+                read_info.max_depth
+            ),
+            (info.start, info.end, info.nodes, info.max_depth)
+        );
+        assert_eq!(read_info.threads.entries, info.threads.entries);
+        assert_eq!(read_info.sample_types, info.sample_types);
+    }
+    #[test]
+    fn trees_alone_round_trip() {
+        let (mut tree, _) = loaded();
+        tree.color_scheme = ColorScheme::Ice;
+        tree.new_color_scheme();
+        let read = decode_tree(&encode_tree(&tree)).unwrap();
+        assert_eq!(nodes(&read), nodes(&tree));
+        assert_eq!(read.root.children[0].color, tree.root.children[0].color);
+        assert_eq!(read.color_scheme, ColorScheme::Ice);
+        let bytes = encode_tree(&tree);
+        assert!(decode_tree(&bytes[..bytes.len() - 1]).is_err());
+    }
+    #[test]
+    fn deep_trees_are_read_without_recursion() {
+        //a chain of nodes written out by hand, one child each but the last
+        let depth = 50_000;
+        let mut bytes = vec![];
+        for i in 0..=depth {
+            write_string(&mut bytes, "f");
+            bytes.push(0);
+            write_u64(&mut bytes, 0);
+            write_u32(&mut bytes, (i < depth) as u32);
+        }
+        let read = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || {
+                let mut node = read_node(&mut Reader { bytes: &bytes }).unwrap();
+                //taken apart a level at a time, dropping the chain whole would recurse
+                let mut read = 0;
+                while let Some(child) = node.children.pop() {
+                    node = child;
+                    read += 1;
+                }
+                read
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(read, depth);
+    }
+    #[test]
+    fn mismatches_are_rejected() {
+        let (tree, info) = loaded();
+        let bytes = encode_cache(&tree, &info, CacheSource::default());
+        let mut version = bytes.clone();
+        version[4] += 1;
+        assert!(cache_source(&version).is_err());
+        assert!(decode_cache(&version).is_err());
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(decode_cache(&corrupt).is_err());
+        assert!(decode_cache(&bytes[..bytes.len() - 1]).is_err());
+    }
+    #[test]
+    fn unknown_color_scheme_is_an_error() {
+        let (mut tree, info) = loaded();
+        let rainbow = encode_cache(&tree, &info, CacheSource::default());
+        tree.color_scheme = ColorScheme::Greyscale;
+        let greyscale = encode_cache(&tree, &info, CacheSource::default());
+        //the scheme is the only byte of the payload that differs
+        let at = (HEADER_LEN..rainbow.len())
+            .find(|i| rainbow[*i] != greyscale[*i])
+            .unwrap();
+        let mut bytes = rainbow;
+        bytes[at] = 9;
+        let checksum = fnv_hash(&bytes[HEADER_LEN..], 0);
+        bytes[HEADER_LEN - 8..HEADER_LEN].copy_from_slice(&checksum.to_le_bytes());
+        match decode_cache(&bytes) {
+            Err(TraceError::Decode(message)) => assert!(message.contains("color scheme")),
+            other => panic!("expected a decode error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use crate::app::tree::*;
+/**  Chrome
+ *   Imports the Chrome Trace Event Format (B/E and X phase events, and M events naming threads)
+ *   Nested events are turned back into call stacks, each event becomes one or more
+ *   traces covering the time it did not spend in its children
+*/
+#[derive(serde::Deserialize)]
+struct ChromeObject {
+    #[serde(rename = "traceEvents")]
+    trace_events: Vec<ChromeEvent>,
+}
+#[derive(serde::Deserialize)]
+struct ChromeEvent {
+    name: Option<String>,
+    ph: Option<String>,
+    ts: Option<f64>,
+    dur: Option<f64>,
+    pid: Option<serde_json::Value>,
+    tid: Option<serde_json::Value>,
+    args: Option<serde_json::Value>,
+}
+///an event with a known start and end on a single thread
+pub struct Span {
+    pub name: String,
+    pub start: u64,
+    pub end: u64,
+}
+///a span that is still open while walking a thread's events in order
+struct OpenSpan {
+    span: Span,
+    cursor: u64,
+    counted: bool,
+}
+///chrome timestamps are floating point microseconds, traces are kept in nanoseconds
+fn to_nanos(event: usize, field: &'static str, micros: f64) -> Result<u64, TraceError> {
+    if micros < 0.0 {
+        return Err(TraceError::NegativeTime {
+            trace: event,
+            field,
+        });
+    }
+    let nanos = (micros * 1000.0).round();
+    if nanos >= u64::MAX as f64 {
+        return Err(TraceError::TimeOverflow { trace: event });
+    }
+    Ok(nanos as u64)
+}
+///the id as a number, when it is one
+fn numeric_id(id: &Option<serde_json::Value>) -> Option<usize> {
+    match id {
+        Some(serde_json::Value::Number(n)) => n.as_u64().map(|n| n as usize),
+        Some(serde_json::Value::String(s)) => s.parse().ok(),
+        None => Some(0),
+        _ => None,
+    }
+}
+///numeric ids are kept, any other id is numbered after the largest numeric one,
+///so a thread named "main" can not end up sharing a number with thread 0
+struct IdSpace {
+    next: usize,
+    named: HashMap<String, usize>,
+}
+impl IdSpace {
+    fn new<'a>(ids: impl Iterator<Item = &'a Option<serde_json::Value>>) -> Self {
+        IdSpace {
+            next: ids.filter_map(numeric_id).max().map_or(0, |max| max + 1),
+            named: HashMap::new(),
+        }
+    }
+    fn id(&mut self, id: &Option<serde_json::Value>) -> usize {
+        if let Some(number) = numeric_id(id) {
+            return number;
+        }
+        let next = &mut self.next;
+        *self.named.entry(id_string(id)).or_insert_with(|| {
+            *next += 1;
+            *next - 1
+        })
+    }
+}
+fn id_string(id: &Option<serde_json::Value>) -> String {
+    match id {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(value) => value.to_string(),
+        None => "0".to_string(),
+    }
+}
+///true if the input looks like a chrome trace rather than the native trace format
+pub fn is_chrome_trace(input: &[u8]) -> bool {
+    let head = &input[..input.len().min(super::HEAD_LEN)];
+    let head = String::from_utf8_lossy(head);
+    head.contains("\"traceEvents\"") || head.contains("\"ph\"")
+}
+///parses a chrome trace, either the bare event array or the object form with `traceEvents`
+pub fn load_chrome(input: &[u8]) -> Result<Vec<Trace>, TraceError> {
+    let first = input.iter().find(|c| !c.is_ascii_whitespace());
+    let events: Vec<ChromeEvent> = if first == Some(&b'{') {
+        serde_json::from_slice::<ChromeObject>(input)?.trace_events
+    } else {
+        serde_json::from_slice(input)?
+    };
+
+    let mut pids = IdSpace::new(events.iter().map(|e| &e.pid));
+    let mut tids = IdSpace::new(events.iter().map(|e| &e.tid));
+    //(pid, tid) pairs are given dense thread indices in the order they first appear
+    let mut threads: HashMap<(String, String), usize> = HashMap::new();
+    let mut ids: Vec<(usize, usize)> = vec![];
+    let mut keys: Vec<(String, String)> = vec![];
+    let mut thread_names: HashMap<(String, String), String> = HashMap::new();
+    let mut process_names: HashMap<String, String> = HashMap::new();
+    let mut spans: Vec<Vec<Span>> = vec![];
+    let mut open: Vec<Vec<(String, u64)>> = vec![];
+    let mut last_time: Vec<u64> = vec![];
+    for (i, event) in events.iter().enumerate() {
+        let ph = event.ph.as_deref().unwrap_or("");
+        let key = (id_string(&event.pid), id_string(&event.tid));
+        if ph == "M" {
+            let name = event.args.as_ref().and_then(|a| a.get("name"));
+            if let Some(name) = name.and_then(|n| n.as_str()) {
+                match event.name.as_deref() {
+                    Some("thread_name") => {
+                        thread_names.insert(key, name.to_string());
+                    }
+                    Some("process_name") => {
+                        process_names.insert(key.0, name.to_string());
+                    }
+                    _ => {}
+                }
+            }
+            continue;
+        }
+        if ph != "B" && ph != "E" && ph != "X" {
+            continue;
+        }
+        let next = threads.len();
+        let thread = *threads.entry(key.clone()).or_insert(next);
+        if thread == spans.len() {
+            keys.push(key);
+            ids.push((pids.id(&event.pid), tids.id(&event.tid)));
+            spans.push(vec![]);
+            open.push(vec![]);
+            last_time.push(0);
+        }
+        let ts = event.ts.ok_or(TraceError::MissingField {
+            trace: i,
+            field: "ts",
+        })?;
+        let start = to_nanos(i, "ts", ts)?;
+        let name = event.name.clone().unwrap_or_default();
+        match ph {
+            "B" => open[thread].push((name, start)),
+            "E" => {
+                //an end without a matching begin is ignored
+                if let Some((name, begin)) = open[thread].pop() {
+                    spans[thread].push(Span {
+                        name,
+                        start: begin,
+                        end: start.max(begin),
+                    });
+                }
+            }
+            _ => {
+                let dur = event.dur.ok_or(TraceError::MissingField {
+                    trace: i,
+                    field: "dur",
+                })?;
+                let dur = to_nanos(i, "dur", dur)?;
+                let end = start
+                    .checked_add(dur)
+                    .ok_or(TraceError::TimeOverflow { trace: i })?;
+                spans[thread].push(Span { name, start, end });
+            }
+        }
+        last_time[thread] = last_time[thread].max(start);
+    }
+    //anything still open when the trace ends is closed at the thread's last timestamp
+    for (thread, stack) in open.into_iter().enumerate() {
+        for (name, start) in stack {
+            spans[thread].push(Span {
+                name,
+                start,
+                end: last_time[thread].max(start),
+            });
+        }
+    }
+
+    let mut traces = vec![];
+    for ((thread_spans, ids), key) in spans.into_iter().zip(ids).zip(keys) {
+        let first = traces.len();
+        spans_to_traces(thread_spans, ids, &mut traces);
+        //names only need to ride along on one trace of each thread
+        if let Some(trace) = traces.get_mut(first) {
+            //without a name event, a thread or process with a named id goes by that id
+            let named = |id: &String| id.parse::<usize>().is_err().then(|| id.clone());
+            trace.thread = ThreadDetails {
+                name: thread_names.remove(&key).or_else(|| named(&key.1)),
+                process_name: process_names.get(&key.0).cloned().or_else(|| named(&key.0)),
+                metadata: Default::default(),
+            };
+        }
+    }
+    if traces.is_empty() {
+        return Err(TraceError::Empty);
+    }
+    Ok(traces)
+}
+///walks one thread's spans in start order, emitting a trace for every stretch of self time.
+///each event adds 1 to `value` on the first trace it emits, so value counts calls.
+///`thread` is the (pid, tid) the traces are given
+pub fn spans_to_traces(mut spans: Vec<Span>, thread: (usize, usize), traces: &mut Vec<Trace>) {
+    spans.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+    let mut stack: Vec<OpenSpan> = vec![];
+    for mut span in spans {
+        while stack.last().map_or(false, |top| top.span.end <= span.start) {
+            close_span(&mut stack, thread, traces);
+        }
+        if let Some(parent) = stack.last_mut() {
+            //events that overlap their parent without nesting are cut off at the parent's end
+            span.end = span.end.min(parent.span.end);
+            let (start, end) = (parent.cursor, span.start);
+            parent.cursor = span.end;
+            emit(&mut stack, start, end, thread, traces);
+        }
+        stack.push(OpenSpan {
+            cursor: span.start,
+            span,
+            counted: false,
+        });
+    }
+    while !stack.is_empty() {
+        close_span(&mut stack, thread, traces);
+    }
+}
+fn close_span(stack: &mut Vec<OpenSpan>, thread: (usize, usize), traces: &mut Vec<Trace>) {
+    let top = stack.last().unwrap();
+    let (start, end) = (top.cursor, top.span.end);
+    if end > start || !top.counted {
+        emit(stack, start, end, thread, traces);
+    }
+    stack.pop();
+}
+///adds a trace for the innermost open span over start..end
+fn emit(
+    stack: &mut [OpenSpan],
+    start: u64,
+    end: u64,
+    thread: (usize, usize),
+    traces: &mut Vec<Trace>,
+) {
+    let Some((top, parents)) = stack.split_last_mut() else {
+        return;
+    };
+    if end <= start && top.counted {
+        return;
+    }
+    let value = if top.counted { 0 } else { 1 };
+    top.counted = true;
+    traces.push(Trace {
+        id: traces.len(),
+        name: top.span.name.clone(),
+        stack: parents
+            .iter()
+            .rev()
+            .map(|p| Stackframe {
+                name: p.span.name.clone(),
+            })
+            .collect(),
+        start,
+        dur: end.saturating_sub(start),
+        value,
+        pid: thread.0,
+        tid: thread.1,
+        metrics: Default::default(),
+        thread: Default::default(),
+    });
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///(call path, start, dur, value) of each trace
+    fn summary(traces: &[Trace]) -> Vec<(String, u64, u64, u64)> {
+        traces
+            .iter()
+            .map(|t| {
+                let mut path: Vec<&str> = t.stack.iter().rev().map(|f| f.name.as_str()).collect();
+                path.push(&t.name);
+                (path.join(";"), t.start, t.dur, t.value)
+            })
+            .collect()
+    }
+    fn span(name: &str, start: u64, end: u64) -> Span {
+        Span {
+            name: name.to_string(),
+            start,
+            end,
+        }
+    }
+    fn trace(path: &str, start: u64, dur: u64, value: u64) -> (String, u64, u64, u64) {
+        (path.to_string(), start, dur, value)
+    }
+
+    #[test]
+    fn begin_end_and_complete_events_nest() {
+        let input = br#"{"traceEvents": [
+            {"name": "main", "ph": "B", "ts": 0, "pid": 1, "tid": 1},
+            {"name": "parse", "ph": "X", "ts": 2, "dur": 3, "pid": 1, "tid": 1},
+            {"name": "draw", "ph": "B", "ts": 6, "pid": 1, "tid": 1},
+            {"name": "draw", "ph": "E", "ts": 8, "pid": 1, "tid": 1},
+            {"name": "main", "ph": "E", "ts": 10, "pid": 1, "tid": 1}
+        ]}"#;
+        assert_eq!(
+            summary(&load_chrome(input).unwrap()),
+            vec![
+                trace("main", 0, 2000, 1),
+                trace("main;parse", 2000, 3000, 1),
+                trace("main", 5000, 1000, 0),
+                trace("main;draw", 6000, 2000, 1),
+                trace("main", 8000, 2000, 0),
+            ]
+        );
+    }
+    #[test]
+    fn self_time_is_split_around_children() {
+        let spans = vec![
+            span("c", 20, 30),
+            span("a", 0, 100),
+            span("b", 10, 40),
+            span("d", 50, 60),
+            //overlaps the end of its parent without nesting, so it is cut off there
+            span("e", 90, 120),
+            span("f", 130, 130),
+        ];
+        let mut traces = vec![];
+        spans_to_traces(spans, (3, 4), &mut traces);
+        assert_eq!(
+            summary(&traces),
+            vec![
+                trace("a", 0, 10, 1),
+                trace("a;b", 10, 10, 1),
+                trace("a;b;c", 20, 10, 1),
+                trace("a;b", 30, 10, 0),
+                trace("a", 40, 10, 0),
+                trace("a;d", 50, 10, 1),
+                trace("a", 60, 30, 0),
+                trace("a;e", 90, 10, 1),
+                //an event with no duration is still counted as a call
+                trace("f", 130, 0, 1),
+            ]
+        );
+        assert!(traces.iter().all(|t| (t.pid, t.tid) == (3, 4)));
+        let total: u64 = traces.iter().map(|t| t.dur).sum();
+        assert_eq!(total, 100);
+    }
+    #[test]
+    fn unmatched_ends_are_ignored() {
+        let input = br#"[
+            {"name": "stray", "ph": "E", "ts": 1, "pid": 1, "tid": 1},
+            {"name": "a", "ph": "B", "ts": 2, "pid": 1, "tid": 1},
+            {"name": "a", "ph": "E", "ts": 4, "pid": 1, "tid": 1},
+            {"name": "stray", "ph": "E", "ts": 5, "pid": 1, "tid": 1}
+        ]"#;
+        assert_eq!(
+            summary(&load_chrome(input).unwrap()),
+            vec![trace("a", 2000, 2000, 1)]
+        );
+    }
+    #[test]
+    fn unclosed_begins_end_at_the_threads_last_event() {
+        let input = br#"[
+            {"name": "a", "ph": "B", "ts": 0, "pid": 1, "tid": 1},
+            {"name": "b", "ph": "B", "ts": 1, "pid": 1, "tid": 1},
+            {"name": "b", "ph": "E", "ts": 3, "pid": 1, "tid": 1},
+            {"name": "other", "ph": "X", "ts": 0, "dur": 50, "pid": 1, "tid": 2}
+        ]"#;
+        assert_eq!(
+            summary(&load_chrome(input).unwrap()),
+            vec![
+                trace("a", 0, 1000, 1),
+                trace("a;b", 1000, 2000, 1),
+                trace("other", 0, 50000, 1),
+            ]
+        );
+    }
+    #[test]
+    fn named_and_numeric_ids_stay_apart() {
+        let input = br#"[
+            {"name": "a", "ph": "X", "ts": 0, "dur": 1, "pid": 1, "tid": "main"},
+            {"name": "b", "ph": "X", "ts": 0, "dur": 1, "pid": 1, "tid": 0},
+            {"name": "c", "ph": "X", "ts": 0, "dur": 1, "pid": "gpu", "tid": 1},
+            {"name": "d", "ph": "X", "ts": 0, "dur": 1, "pid": 2, "tid": 1}
+        ]"#;
+        let (_, info) = grow_master_tree(&load_chrome(input).unwrap());
+        let threads = &info.threads;
+        assert_eq!(threads.entries.len(), 4);
+        assert_eq!(threads.processes.len(), 3);
+        assert_eq!(threads.thread_name(0), "main");
+        assert_ne!((threads.entries[0].pid, threads.entries[0].tid), (1, 0));
+        assert_ne!(threads.entries[2].pid, 2);
+        assert_eq!(threads.process_name(threads.entries[2].process), "gpu");
+    }
+}
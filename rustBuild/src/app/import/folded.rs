@@ -0,0 +1,193 @@
+use crate::app::tree::*;
+/**  Folded
+ *   Imports Brendan Gregg's folded (collapsed) stacks, one `a;b;c 123` stack per line
+ *   The format has no timestamps, so each file is laid out as its own snapshot in time:
+ *   stacks follow each other inside a fixed window with a duration equal to their count
+*/
+///one folded line, with the stack running from the outermost frame to the leaf
+struct FoldedStack<'a> {
+    frames: Vec<&'a str>,
+    count: u64,
+}
+///true if the name or first line of the input looks like folded stacks
+pub fn is_folded(name: &str, input: &[u8]) -> bool {
+    if name.ends_with(".folded") || name.ends_with(".collapsed") {
+        return true;
+    }
+    let head = String::from_utf8_lossy(&input[..input.len().min(super::HEAD_LEN)]);
+    let Some(line) = head.lines().find(|l| !l.trim().is_empty()) else {
+        return false;
+    };
+    let line = line.trim_end();
+    !line.starts_with('[')
+        && !line.starts_with('{')
+        && line.rsplit_once(' ').map_or(false, |(stack, count)| {
+            !stack.is_empty() && count.parse::<u64>().is_ok()
+        })
+}
+fn parse_folded(input: &[u8]) -> Result<Vec<FoldedStack<'_>>, TraceError> {
+    let text = std::str::from_utf8(input).map_err(|e| TraceError::Syntax {
+        line: 1 + input[..e.valid_up_to()]
+            .iter()
+            .filter(|c| **c == b'\n')
+            .count(),
+        column: 0,
+        message: "file is not valid UTF-8".to_string(),
+    })?;
+    let mut stacks = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let syntax = |column: usize, message: &str| TraceError::Syntax {
+            line: i + 1,
+            column,
+            message: message.to_string(),
+        };
+        let (stack, count) = line
+            .rsplit_once(' ')
+            .ok_or_else(|| syntax(line.len(), "expected a count after the stack"))?;
+        let count = count
+            .parse::<u64>()
+            .map_err(|_| syntax(stack.len() + 1, "the count is not a whole number"))?;
+        let frames: Vec<&str> = stack.trim().split(';').collect();
+        stacks.push(FoldedStack { frames, count });
+    }
+    Ok(stacks)
+}
+///parses a single folded file
+pub fn load_folded(input: &[u8]) -> Result<Vec<Trace>, TraceError> {
+    load_folded_sequence(&[input])
+}
+///parses several folded files, each one becoming the next slice of time
+pub fn load_folded_sequence(inputs: &[&[u8]]) -> Result<Vec<Trace>, TraceError> {
+    let snapshots = inputs
+        .iter()
+        .map(|input| parse_folded(input))
+        .collect::<Result<Vec<_>, _>>()?;
+    //every snapshot gets a window as long as the largest one so slices line up evenly
+    let mut window = 0;
+    let mut first = 0;
+    for stacks in &snapshots {
+        let mut length: u64 = 0;
+        for (i, stack) in stacks.iter().enumerate() {
+            length = length
+                .checked_add(stack.count)
+                .ok_or(TraceError::TimeOverflow { trace: first + i })?;
+        }
+        window = window.max(length);
+        first += stacks.len();
+    }
+    let overflow = |trace| TraceError::TimeOverflow { trace };
+    let mut traces = vec![];
+    for (i, stacks) in snapshots.iter().enumerate() {
+        let mut start = (i as u64)
+            .checked_mul(window)
+            .ok_or(overflow(traces.len()))?;
+        for stack in stacks {
+            let Some((name, parents)) = stack.frames.split_last() else {
+                continue;
+            };
+            let end = start
+                .checked_add(stack.count)
+                .ok_or(overflow(traces.len()))?;
+            traces.push(Trace {
+                id: traces.len(),
+                name: name.to_string(),
+                stack: parents
+                    .iter()
+                    .rev()
+                    .map(|p| Stackframe {
+                        name: p.to_string(),
+                    })
+                    .collect(),
+                start,
+                dur: stack.count,
+                value: stack.count,
+                pid: 0,
+                tid: 0,
+                metrics: Default::default(),
+                thread: Default::default(),
+            });
+            start = end;
+        }
+    }
+    if traces.is_empty() {
+        return Err(TraceError::Empty);
+    }
+    Ok(traces)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_may_contain_spaces() {
+        let input = b"main;<Vec<T> as Drop>::drop 12\n\nmain;operator new(unsigned long) 3  \n";
+        let stacks = parse_folded(input).unwrap();
+        assert_eq!(stacks.len(), 2);
+        assert_eq!(stacks[0].frames, vec!["main", "<Vec<T> as Drop>::drop"]);
+        assert_eq!(stacks[0].count, 12);
+        assert_eq!(
+            stacks[1].frames,
+            vec!["main", "operator new(unsigned long)"]
+        );
+        assert_eq!(stacks[1].count, 3);
+    }
+    #[test]
+    fn bad_lines_report_where() {
+        let error = |input: &[u8]| match parse_folded(input) {
+            Err(TraceError::Syntax { line, column, .. }) => (line, column),
+            _ => panic!("expected a syntax error"),
+        };
+        assert_eq!(error(b"main;f 1\nmain;g\n"), (2, 6));
+        assert_eq!(error(b"main;f 1\n\nmain;g 2.5\n"), (3, 7));
+        assert_eq!(error(b"main;f 1\nmain;\xff 2\n").0, 2);
+    }
+    #[test]
+    fn stacks_follow_each_other() {
+        let traces = load_folded(b"a;b;c 5\na;d 2\n").unwrap();
+        let spans: Vec<_> = traces
+            .iter()
+            .map(|t| (t.name.as_str(), t.start, t.dur))
+            .collect();
+        assert_eq!(spans, vec![("c", 0, 5), ("d", 5, 2)]);
+        let parents: Vec<_> = traces[0].stack.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(parents, vec!["b", "a"]);
+    }
+    #[test]
+    fn snapshots_share_the_largest_window() {
+        let first: &[u8] = b"a;b 10\na 20\n";
+        let second: &[u8] = b"a;b 4\n";
+        let third: &[u8] = b"a 1\na;c 1\n";
+        let traces = load_folded_sequence(&[first, second, third]).unwrap();
+        let starts: Vec<_> = traces.iter().map(|t| (t.name.as_str(), t.start)).collect();
+        assert_eq!(
+            starts,
+            vec![("b", 0), ("a", 10), ("b", 30), ("a", 60), ("c", 61)]
+        );
+    }
+    #[test]
+    fn empty_snapshots_are_an_error() {
+        assert_eq!(
+            load_folded_sequence(&[b"\n", b""]).unwrap_err(),
+            TraceError::Empty
+        );
+    }
+    #[test]
+    fn counts_past_the_largest_time_are_errors() {
+        let half = format!("a {}\n", u64::MAX / 2 + 1);
+        let twice = half.repeat(2);
+        assert_eq!(
+            load_folded(twice.as_bytes()).unwrap_err(),
+            TraceError::TimeOverflow { trace: 1 }
+        );
+        //each snapshot fits, but the second one starts a whole window later
+        let half = half.as_bytes();
+        assert_eq!(
+            load_folded_sequence(&[half, half]).unwrap_err(),
+            TraceError::TimeOverflow { trace: 1 }
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::app::tree::*;
+/**  Perf
+ *   Imports the text written by `perf script`, a header line per sample followed by its stack:
+ *       comm  pid/tid [cpu] 1234.567890: 250000 cycles:
+ *               ffffffff81234567 func+0x12 (/lib/module.so)
+ *   Without callchains there are no stack lines, the header ends with the sampled ip instead:
+ *               comm  pid [cpu] 1234.567890: 250000 cycles:  ffffffff81234567 func+0x12 (/lib/module.so)
+ *   A sample lasts until the next sample on its thread, capped at the thread's median gap
+ *   so idle time between samples is not charged to the last stack seen
+ *   The period is also kept as a metric named after the sampled event, such as `cycles`
+ *   Threads keep their pid and tid, the tree gives them dense indices, and are named by command
+*/
+struct PerfSample {
+    ///the command the thread was running, used as the thread's name
+    comm: String,
+    thread: (u64, u64),
+    time: u64,
+    period: u64,
+    event: Option<String>,
+    frames: Vec<String>,
+    ///the frame at the end of the header, the whole stack when there are no stack lines
+    ip_frame: Option<String>,
+}
+///the header of one sample, None if the line is not a sample header
+fn parse_header(line: &str) -> Option<PerfSample> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let time_index = tokens.iter().position(|t| {
+        t.strip_suffix(':')
+            .map_or(false, |t| t.contains('.') && t.parse::<f64>().is_ok())
+    })?;
+    let seconds: f64 = tokens[time_index].trim_end_matches(':').parse().ok()?;
+    //the cpu column is optional, the thread column comes right before it
+    let mut thread_index = time_index.checked_sub(1)?;
+    if tokens[thread_index].starts_with('[') {
+        thread_index = thread_index.checked_sub(1)?;
+    }
+    let thread = match tokens[thread_index].split_once('/') {
+        Some((pid, tid)) => (pid.parse().ok()?, tid.parse().ok()?),
+        None => (0, tokens[thread_index].parse().ok()?),
+    };
+    //then an optional period, an optional event such as `cycles:` and an optional ip
+    let mut next = time_index + 1;
+    let period = tokens.get(next).and_then(|t| t.parse().ok());
+    if period.is_some() {
+        next += 1;
+    }
+    let event = tokens
+        .get(next)
+        .and_then(|t| t.strip_suffix(':'))
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string());
+    if event.is_some() {
+        next += 1;
+    }
+    let ip_frame = (next < tokens.len()).then(|| parse_frame(&tokens[next..].join(" ")));
+    if seconds < 0.0 || seconds * 1e9 >= u64::MAX as f64 {
+        return None;
+    }
+    Some(PerfSample {
+        comm: tokens[..thread_index].join(" "),
+        thread,
+        time: (seconds * 1e9).round() as u64,
+        period: period.unwrap_or(1),
+        event,
+        frames: vec![],
+        ip_frame,
+    })
+}
+///true for a stack line, which starts with the frame's address
+fn is_frame(line: &str) -> bool {
+    line.split_whitespace().next().map_or(false, |address| {
+        address.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+///the function name in a stack line such as `ffffffff81234567 func+0x12 (/lib/module.so)`
+fn parse_frame(line: &str) -> String {
+    let line = line.trim();
+    let (address, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let (symbol, dso) = match rest.rfind(" (") {
+        Some(i) => (rest[..i].trim(), rest[i + 2..].trim_end_matches(')')),
+        None => (rest.trim(), ""),
+    };
+    let symbol = match symbol.rfind("+0x") {
+        Some(i) => &symbol[..i],
+        None => symbol,
+    };
+    if !symbol.is_empty() && symbol != "[unknown]" {
+        symbol.to_string()
+    } else if !dso.is_empty() && dso != "[unknown]" {
+        format!("[{}]", dso.rsplit('/').next().unwrap_or(dso))
+    } else {
+        address.to_string()
+    }
+}
+///true if the name or first sample header looks like `perf script` output
+pub fn is_perf_script(name: &str, input: &[u8]) -> bool {
+    if name.ends_with(".perf") || name.ends_with(".script") {
+        return true;
+    }
+    let head = String::from_utf8_lossy(&input[..input.len().min(super::HEAD_LEN)]);
+    head.lines()
+        .find(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .map_or(false, |l| parse_header(l).is_some())
+}
+///parses `perf script` output into one trace per sample
+pub fn load_perf_script(input: &[u8]) -> Result<Vec<Trace>, TraceError> {
+    let text = String::from_utf8_lossy(input);
+    let mut samples: Vec<PerfSample> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        //both kinds of line may be indented, headers are told apart by their timestamp
+        if let Some(sample) = parse_header(line) {
+            samples.push(sample);
+        } else if is_frame(line) {
+            match samples.last_mut() {
+                Some(sample) => sample.frames.push(parse_frame(line)),
+                None => {
+                    return Err(TraceError::Syntax {
+                        line: i + 1,
+                        column: 0,
+                        message: "stack line before the first sample header".to_string(),
+                    })
+                }
+            }
+        } else {
+            return Err(TraceError::Syntax {
+                line: i + 1,
+                column: 0,
+                message: "expected a sample header with a thread and timestamp".to_string(),
+            });
+        }
+    }
+
+    //(pid, tid) pairs are given dense thread indices in the order they first appear
+    let mut threads: HashMap<(u64, u64), usize> = HashMap::new();
+    let mut times: Vec<Vec<u64>> = vec![];
+    for sample in &samples {
+        let next = threads.len();
+        let thread = *threads.entry(sample.thread).or_insert(next);
+        if thread == times.len() {
+            times.push(vec![]);
+        }
+        times[thread].push(sample.time);
+    }
+    let median_gap: Vec<u64> = times
+        .iter_mut()
+        .map(|t| {
+            t.sort_unstable();
+            let mut gaps: Vec<u64> = t.windows(2).map(|w| w[1] - w[0]).collect();
+            gaps.sort_unstable();
+            gaps.get(gaps.len() / 2).copied().unwrap_or(1).max(1)
+        })
+        .collect();
+
+    let mut traces = vec![];
+    let mut named = vec![false; times.len()];
+    for sample in samples {
+        let thread = threads[&sample.thread];
+        let thread_times = &times[thread];
+        let next = thread_times.partition_point(|t| *t <= sample.time);
+        let gap = thread_times
+            .get(next)
+            .map_or(median_gap[thread], |t| t - sample.time);
+        let mut frames = sample.frames;
+        if frames.is_empty() {
+            frames.push(sample.ip_frame.unwrap_or_else(|| "[unknown]".to_string()));
+        }
+        let mut frames = frames.into_iter();
+        let name = frames.next().unwrap();
+        let mut details = ThreadDetails::default();
+        if !named[thread] && !sample.comm.is_empty() {
+            named[thread] = true;
+            details.name = Some(format!("{} {}", sample.comm, sample.thread.1));
+        }
+        traces.push(Trace {
+            id: traces.len(),
+            name,
+            stack: frames.map(|name| Stackframe { name }).collect(),
+            start: sample.time,
+            dur: gap.min(median_gap[thread]),
+            value: sample.period,
+            pid: sample.thread.0 as usize,
+            tid: sample.thread.1 as usize,
+            metrics: sample
+                .event
+                .map(|event| BTreeMap::from([(event, sample.period)]))
+                .unwrap_or_default(),
+            thread: details,
+        });
+    }
+    if traces.is_empty() {
+        return Err(TraceError::Empty);
+    }
+    Ok(traces)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack_of(trace: &Trace) -> Vec<&str> {
+        let mut stack: Vec<&str> = trace.stack.iter().map(|f| f.name.as_str()).collect();
+        stack.insert(0, &trace.name);
+        stack
+    }
+    #[test]
+    fn callchain_samples() {
+        let input = b"\
+perf 12/13 [001] 10.000000: 250000 cycles:
+\tffffffff81000010 leaf+0x10 (/lib/a.so)
+\t4005d0 main (/bin/app)
+
+perf 12/13 [001] 10.000100: 250000 cycles:
+\t7f0000000000 [unknown] (/lib/libc.so.6)
+\t4005d0 main (/bin/app)
+";
+        let traces = load_perf_script(input).unwrap();
+        assert_eq!(traces.len(), 2);
+        assert_eq!(stack_of(&traces[0]), ["leaf", "main"]);
+        assert_eq!(stack_of(&traces[1]), ["[libc.so.6]", "main"]);
+        assert_eq!((traces[0].pid, traces[0].tid), (12, 13));
+        assert_eq!(traces[0].start, 10_000_000_000);
+        assert_eq!(traces[0].dur, 100_000);
+        assert_eq!(traces[0].metrics.get("cycles"), Some(&250000));
+        assert_eq!(traces[0].thread.name.as_deref(), Some("perf 13"));
+    }
+    #[test]
+    fn samples_without_callchains() {
+        //the command is padded to 16 columns, so headers start with spaces
+        let input = b"\
+            perf 12 [003] 10.000000:          1 cycles:  ffffffff81000010 leaf+0x10 ([kernel.kallsyms])
+     sleepy task 12 [003] 10.000200:          1 cycles:  4005d0 main (/bin/app)
+            perf 12 [003] 10.000400:          1 cycles:
+";
+        let traces = load_perf_script(input).unwrap();
+        assert_eq!(traces.len(), 3);
+        assert_eq!(stack_of(&traces[0]), ["leaf"]);
+        assert_eq!(stack_of(&traces[1]), ["main"]);
+        assert_eq!(stack_of(&traces[2]), ["[unknown]"]);
+        assert_eq!((traces[0].pid, traces[0].tid), (0, 12));
+        assert_eq!(traces[0].thread.name.as_deref(), Some("perf 12"));
+    }
+    #[test]
+    fn stray_lines_are_syntax_errors() {
+        assert!(matches!(
+            load_perf_script(b"\tffffffff81000010 leaf (/lib/a.so)\n"),
+            Err(TraceError::Syntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            load_perf_script(b"perf 12 [003] 10.000000: 1 cycles:\nnot a frame\n"),
+            Err(TraceError::Syntax { line: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+use crate::app::tree::*;
+/**  Pprof
+ *   Imports pprof `profile.proto` files (optionally gzipped) as written by Go and pprof-rs
+ *   Samples without a timestamp label are laid out one after another like folded stacks
+ *   Every sample type is kept as a named metric, the chosen one also becomes the value
+*/
+#[derive(Clone, PartialEq, prost::Message)]
+struct Profile {
+    #[prost(message, repeated, tag = "1")]
+    sample_type: Vec<ValueType>,
+    #[prost(message, repeated, tag = "2")]
+    sample: Vec<Sample>,
+    #[prost(message, repeated, tag = "4")]
+    location: Vec<Location>,
+    #[prost(message, repeated, tag = "5")]
+    function: Vec<Function>,
+    #[prost(string, repeated, tag = "6")]
+    string_table: Vec<String>,
+    #[prost(message, optional, tag = "11")]
+    period_type: Option<ValueType>,
+    #[prost(int64, tag = "12")]
+    period: i64,
+    #[prost(int64, tag = "14")]
+    default_sample_type: i64,
+}
+#[derive(Clone, PartialEq, prost::Message)]
+struct ValueType {
+    #[prost(int64, tag = "1")]
+    r#type: i64,
+    #[prost(int64, tag = "2")]
+    unit: i64,
+}
+#[derive(Clone, PartialEq, prost::Message)]
+struct Sample {
+    #[prost(uint64, repeated, tag = "1")]
+    location_id: Vec<u64>,
+    #[prost(int64, repeated, tag = "2")]
+    value: Vec<i64>,
+    #[prost(message, repeated, tag = "3")]
+    label: Vec<Label>,
+}
+#[derive(Clone, PartialEq, prost::Message)]
+struct Label {
+    #[prost(int64, tag = "1")]
+    key: i64,
+    #[prost(int64, tag = "2")]
+    str: i64,
+    #[prost(int64, tag = "3")]
+    num: i64,
+}
+#[derive(Clone, PartialEq, prost::Message)]
+struct Location {
+    #[prost(uint64, tag = "1")]
+    id: u64,
+    #[prost(uint64, tag = "3")]
+    address: u64,
+    #[prost(message, repeated, tag = "4")]
+    line: Vec<Line>,
+}
+#[derive(Clone, PartialEq, prost::Message)]
+struct Line {
+    #[prost(uint64, tag = "1")]
+    function_id: u64,
+}
+#[derive(Clone, PartialEq, prost::Message)]
+struct Function {
+    #[prost(uint64, tag = "1")]
+    id: u64,
+    #[prost(int64, tag = "2")]
+    name: i64,
+}
+///label keys that carry a sample's timestamp in nanoseconds
+const TIMESTAMP_LABELS: &[&str] = &["timestamp", "timestamp_ns"];
+///label keys that say which thread a sample came from
+const THREAD_LABELS: &[&str] = &["thread", "thread_id", "thread id", "tid", "thread_name"];
+
+///true if the name says this is a pprof profile, or it is gzip holding what looks like one
+pub fn is_pprof(name: &str, input: &[u8]) -> bool {
+    if name.ends_with(".pprof") || name.ends_with(".pb") || name.ends_with(".pb.gz") {
+        return true;
+    }
+    if !input.starts_with(&[0x1f, 0x8b]) {
+        return false;
+    }
+    //only the start is unpacked, the input may be just the head of the file
+    let mut head = vec![];
+    let mut decoder = flate2::read::GzDecoder::new(input).take(super::HEAD_LEN as u64);
+    let mut buffer = [0; 512];
+    while let Ok(read) = decoder.read(&mut buffer) {
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    looks_like_profile(&head)
+}
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+///true if the bytes start with fields of a `Profile` message in the wire types it uses,
+///bytes cut off part way through a field are fine
+fn looks_like_profile(mut bytes: &[u8]) -> bool {
+    let mut fields = 0;
+    while let Some(key) = read_varint(&mut bytes) {
+        let length_delimited = match (key >> 3, key & 7) {
+            (1..=6 | 11 | 13, 2) => true,
+            (7..=10 | 12..=14, 0) => false,
+            _ => return false,
+        };
+        fields += 1;
+        //a number, or the length of what follows
+        let Some(value) = read_varint(&mut bytes) else {
+            break;
+        };
+        if length_delimited {
+            match usize::try_from(value) {
+                Ok(len) if len <= bytes.len() => bytes = &bytes[len..],
+                _ => break,
+            }
+        }
+    }
+    fields > 0
+}
+fn decode(input: &[u8]) -> Result<Profile, TraceError> {
+    let decode_error = |e: &dyn std::fmt::Display| TraceError::Decode(e.to_string());
+    if input.starts_with(&[0x1f, 0x8b]) {
+        let mut bytes = vec![];
+        flate2::read::GzDecoder::new(input)
+            .read_to_end(&mut bytes)
+            .map_err(|e| decode_error(&e))?;
+        <Profile as prost::Message>::decode(bytes.as_slice()).map_err(|e| decode_error(&e))
+    } else {
+        <Profile as prost::Message>::decode(input).map_err(|e| decode_error(&e))
+    }
+}
+impl Profile {
+    fn string(&self, index: i64) -> &str {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| self.string_table.get(i))
+            .map_or("", |s| s.as_str())
+    }
+    fn sample_type_names(&self) -> Vec<String> {
+        self.sample_type
+            .iter()
+            .map(|t| format!("{} ({})", self.string(t.r#type), self.string(t.unit)))
+            .collect()
+    }
+    ///the sample type asked for by name, or the profile's default, or the last one as pprof does
+    fn pick_sample_type(&self, wanted: &Option<String>) -> usize {
+        let names = self.sample_type_names();
+        if let Some(i) = wanted
+            .as_ref()
+            .and_then(|w| names.iter().position(|n| n == w))
+        {
+            return i;
+        }
+        let default = self.string(self.default_sample_type);
+        self.sample_type
+            .iter()
+            .position(|t| self.default_sample_type != 0 && self.string(t.r#type) == default)
+            .unwrap_or(names.len().saturating_sub(1))
+    }
+}
+///parses a pprof profile, using the named sample type as each trace's value, along with the
+///names of all its sample types in the form shown in the sample type dropdown
+pub fn load_pprof(
+    input: &[u8],
+    sample_type: &Option<String>,
+) -> Result<(Vec<Trace>, Vec<String>), TraceError> {
+    let profile = decode(input)?;
+    let value_index = profile.pick_sample_type(sample_type);
+    let unit = profile
+        .sample_type
+        .get(value_index)
+        .map_or("", |t| profile.string(t.unit));
+    let period = match &profile.period_type {
+        Some(t) if profile.string(t.unit) == "nanoseconds" => profile.period.max(1) as u64,
+        _ => 1,
+    };
+
+    let functions: HashMap<u64, &str> = profile
+        .function
+        .iter()
+        .map(|f| (f.id, profile.string(f.name)))
+        .collect();
+    //each location expands to its inlined frames, innermost first
+    let locations: HashMap<u64, Vec<String>> = profile
+        .location
+        .iter()
+        .map(|l| {
+            let mut frames: Vec<String> = l
+                .line
+                .iter()
+                .map(|line| functions.get(&line.function_id).unwrap_or(&"").to_string())
+                .collect();
+            if frames.is_empty() {
+                frames.push(format!("{:#x}", l.address));
+            }
+            (l.id, frames)
+        })
+        .collect();
+
+    let metric_names = profile.sample_type_names();
+    let mut threads: HashMap<String, usize> = HashMap::new();
+    let mut named = HashSet::new();
+    let mut traces = vec![];
+    let mut next_start = 0;
+    for (i, sample) in profile.sample.iter().enumerate() {
+        //negative values only show up in diffed profiles and are treated as zero
+        let value = sample.value.get(value_index).copied().unwrap_or(0).max(0) as u64;
+        let mut timestamp = None;
+        let mut thread = None;
+        for label in &sample.label {
+            let key = profile.string(label.key);
+            if TIMESTAMP_LABELS.contains(&key) {
+                timestamp = Some(label.num);
+            } else if THREAD_LABELS.contains(&key) {
+                thread = Some(if label.str != 0 {
+                    profile.string(label.str).to_string()
+                } else {
+                    label.num.to_string()
+                });
+            }
+        }
+        let (start, dur) = match timestamp {
+            Some(ts) if ts < 0 => {
+                return Err(TraceError::NegativeTime {
+                    trace: i,
+                    field: "timestamp",
+                })
+            }
+            Some(ts) if unit == "nanoseconds" => (ts as u64, value),
+            Some(ts) => (ts as u64, period),
+            None => {
+                let start = next_start;
+                next_start += value;
+                (start, value)
+            }
+        };
+        if start.checked_add(dur).is_none() {
+            return Err(TraceError::TimeOverflow { trace: i });
+        }
+        let next = threads.len();
+        let thread = thread.unwrap_or_default();
+        let tid = *threads.entry(thread.clone()).or_insert(next);
+
+        let mut frames = sample
+            .location_id
+            .iter()
+            .flat_map(|id| locations.get(id).into_iter().flatten());
+        let Some(name) = frames.next() else {
+            continue;
+        };
+        //a thread label holding text rather than a number names the thread
+        let mut details = ThreadDetails::default();
+        if named.insert(tid) && thread.parse::<i64>().is_err() && !thread.is_empty() {
+            details.name = Some(thread);
+        }
+        traces.push(Trace {
+            id: traces.len(),
+            name: name.clone(),
+            stack: frames.map(|f| Stackframe { name: f.clone() }).collect(),
+            start,
+            dur,
+            value,
+            pid: 0,
+            tid,
+            metrics: metric_names
+                .iter()
+                .cloned()
+                .zip(sample.value.iter().map(|v| (*v).max(0) as u64))
+                .collect(),
+            thread: details,
+        });
+    }
+    if traces.is_empty() {
+        return Err(TraceError::Empty);
+    }
+    Ok((traces, metric_names))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use std::io::Write;
+
+    ///strings are looked up by their index in the table, "" is always first
+    fn string(profile: &mut Profile, s: &str) -> i64 {
+        if let Some(i) = profile.string_table.iter().position(|t| t == s) {
+            return i as i64;
+        }
+        profile.string_table.push(s.to_string());
+        profile.string_table.len() as i64 - 1
+    }
+    ///a profile with one function per name and the sample types given as (type, unit)
+    fn profile(functions: &[&str], sample_types: &[(&str, &str)]) -> Profile {
+        let mut profile = Profile {
+            string_table: vec![String::new()],
+            ..Default::default()
+        };
+        for (id, name) in functions.iter().enumerate() {
+            let name = string(&mut profile, name);
+            let id = id as u64 + 1;
+            profile.function.push(Function { id, name });
+            profile.location.push(Location {
+                id,
+                address: 0,
+                line: vec![Line { function_id: id }],
+            });
+        }
+        for (r#type, unit) in sample_types {
+            let sample_type = ValueType {
+                r#type: string(&mut profile, r#type),
+                unit: string(&mut profile, unit),
+            };
+            profile.sample_type.push(sample_type);
+        }
+        profile
+    }
+    ///a sample of locations given leaf first, with (key, str, num) labels
+    fn sample(
+        profile: &mut Profile,
+        locations: &[u64],
+        value: Vec<i64>,
+        labels: &[(&str, &str, i64)],
+    ) {
+        let label = labels
+            .iter()
+            .map(|(key, text, num)| Label {
+                key: string(profile, key),
+                str: if text.is_empty() {
+                    0
+                } else {
+                    string(profile, text)
+                },
+                num: *num,
+            })
+            .collect();
+        profile.sample.push(Sample {
+            location_id: locations.to_vec(),
+            value,
+            label,
+        });
+    }
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn sample_type_is_picked_by_name_then_default_then_last() {
+        let mut profile = profile(
+            &[],
+            &[
+                ("samples", "count"),
+                ("cpu", "nanoseconds"),
+                ("alloc", "bytes"),
+            ],
+        );
+        assert_eq!(profile.pick_sample_type(&None), 2);
+        assert_eq!(
+            profile.pick_sample_type(&Some("samples (count)".to_string())),
+            0
+        );
+        profile.default_sample_type = string(&mut profile, "cpu");
+        assert_eq!(profile.pick_sample_type(&None), 1);
+        assert_eq!(
+            profile.pick_sample_type(&Some("missing (count)".to_string())),
+            1
+        );
+        assert_eq!(
+            profile.pick_sample_type(&Some("alloc (bytes)".to_string())),
+            2
+        );
+        assert_eq!(
+            profile.sample_type_names(),
+            vec!["samples (count)", "cpu (nanoseconds)", "alloc (bytes)"]
+        );
+    }
+    #[test]
+    fn timestamps_and_threads_come_from_labels() {
+        let mut profile = profile(
+            &["main", "work"],
+            &[("samples", "count"), ("cpu", "nanoseconds")],
+        );
+        profile.period_type = Some(ValueType {
+            r#type: string(&mut profile, "cpu"),
+            unit: string(&mut profile, "nanoseconds"),
+        });
+        profile.period = 100;
+        sample(
+            &mut profile,
+            &[2, 1],
+            vec![1, 300],
+            &[("timestamp_ns", "", 5000), ("thread", "worker", 0)],
+        );
+        sample(&mut profile, &[1], vec![2, 700], &[("tid", "", 42)]);
+        sample(
+            &mut profile,
+            &[2, 1],
+            vec![1, 100],
+            &[("thread", "worker", 0)],
+        );
+        let input = profile.encode_to_vec();
+
+        let (traces, sample_types) = load_pprof(&input, &None).unwrap();
+        assert_eq!(sample_types, vec!["samples (count)", "cpu (nanoseconds)"]);
+        let spans: Vec<_> = traces
+            .iter()
+            .map(|t| (t.name.as_str(), t.start, t.dur, t.value, t.tid))
+            .collect();
+        //cpu time is in nanoseconds, so it is each sample's duration
+        //and samples without a timestamp follow each other from 0
+        assert_eq!(
+            spans,
+            vec![
+                ("work", 5000, 300, 300, 0),
+                ("main", 0, 700, 700, 1),
+                ("work", 700, 100, 100, 0)
+            ]
+        );
+        assert_eq!(traces[0].stack[0].name, "main");
+        assert_eq!(traces[0].thread.name.as_deref(), Some("worker"));
+        assert_eq!(traces[1].thread.name, None);
+        assert_eq!(traces[2].thread.name, None);
+        assert_eq!(traces[0].metrics["samples (count)"], 1);
+
+        //a count is not a time, so a timed sample lasts one period instead
+        let (traces, _) = load_pprof(&input, &Some("samples (count)".to_string())).unwrap();
+        assert_eq!(
+            (traces[0].start, traces[0].dur, traces[0].value),
+            (5000, 100, 1)
+        );
+    }
+    #[test]
+    fn negative_timestamps_are_errors() {
+        let mut profile = profile(&["main"], &[("samples", "count")]);
+        sample(&mut profile, &[1], vec![1], &[("timestamp", "", -1)]);
+        assert!(matches!(
+            load_pprof(&profile.encode_to_vec(), &None),
+            Err(TraceError::NegativeTime { trace: 0, .. })
+        ));
+    }
+    #[test]
+    fn only_gzipped_profiles_are_detected() {
+        let mut profile = profile(&["main"], &[("samples", "count")]);
+        for i in 0..2000 {
+            let name = format!("function_{}", i * 7919 % 10007);
+            string(&mut profile, &name);
+        }
+        sample(&mut profile, &[1], vec![3], &[]);
+        let compressed = gzip(&profile.encode_to_vec());
+        assert!(compressed.len() > super::super::HEAD_LEN);
+        assert!(is_pprof("profile", &compressed));
+        //natively only the head of the file is looked at
+        assert!(is_pprof("profile", &compressed[..super::super::HEAD_LEN]));
+        assert_eq!(load_pprof(&compressed, &None).unwrap().0[0].value, 3);
+
+        assert!(!is_pprof("trace.json.gz", &gzip(b"[{\"name\": \"main\"}]")));
+        assert!(!is_pprof("stacks.gz", &gzip(b"main;work 10\n")));
+        assert!(!is_pprof("empty.gz", &gzip(b"")));
+        assert!(!is_pprof("profile", &profile.encode_to_vec()));
+        assert!(is_pprof("cpu.pb.gz", b""));
+    }
+}
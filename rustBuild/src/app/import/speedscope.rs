@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+
+use super::chrome::*;
+use crate::app::data::DataChoices;
+use crate::app::tree::*;
+/**  Speedscope
+ *   Reads and writes speedscope's JSON file format
+ *   Each profile in a file becomes a thread, evented profiles keep their timing and
+ *   sampled profiles are laid out one sample after another like folded stacks
+*/
+const SCHEMA: &str = "https://www.speedscope.app/file-format-schema.json";
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct SpeedscopeFile {
+    #[serde(rename = "$schema")]
+    schema: String,
+    shared: Shared,
+    profiles: Vec<Profile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exporter: Option<String>,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Shared {
+    frames: Vec<Frame>,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Frame {
+    name: String,
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Profile {
+    Evented {
+        name: String,
+        unit: String,
+        #[serde(rename = "startValue")]
+        start_value: f64,
+        #[serde(rename = "endValue")]
+        end_value: f64,
+        events: Vec<Event>,
+    },
+    Sampled {
+        name: String,
+        unit: String,
+        #[serde(rename = "startValue")]
+        start_value: f64,
+        #[serde(rename = "endValue")]
+        end_value: f64,
+        samples: Vec<Vec<usize>>,
+        weights: Vec<f64>,
+    },
+}
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Event {
+    #[serde(rename = "type")]
+    kind: String,
+    frame: usize,
+    at: f64,
+}
+///traces are kept in nanoseconds, units without a time are kept as they are
+fn nanos_per_unit(unit: &str) -> f64 {
+    match unit {
+        "seconds" => 1e9,
+        "milliseconds" => 1e6,
+        "microseconds" => 1e3,
+        _ => 1.0,
+    }
+}
+fn to_nanos(trace: usize, at: f64, scale: f64) -> Result<u64, TraceError> {
+    if at < 0.0 {
+        return Err(TraceError::NegativeTime { trace, field: "at" });
+    }
+    let nanos = (at * scale).round();
+    if nanos >= u64::MAX as f64 {
+        return Err(TraceError::TimeOverflow { trace });
+    }
+    Ok(nanos as u64)
+}
+///true if the input names the speedscope schema
+pub fn is_speedscope(input: &[u8]) -> bool {
+    String::from_utf8_lossy(&input[..input.len().min(super::HEAD_LEN)]).contains("speedscope")
+}
+///parses a speedscope file, every profile in it becomes its own thread
+pub fn load_speedscope(input: &[u8]) -> Result<Vec<Trace>, TraceError> {
+    let file: SpeedscopeFile = serde_json::from_slice(input)?;
+    let frame_name = |i: usize, index: usize| {
+        file.shared
+            .frames
+            .get(index)
+            .map(|f| f.name.clone())
+            .ok_or(TraceError::MissingField {
+                trace: i,
+                field: "frame",
+            })
+    };
+    let mut traces = vec![];
+    for (thread, profile) in file.profiles.iter().enumerate() {
+        let first = traces.len();
+        match profile {
+            Profile::Evented {
+                unit,
+                end_value,
+                events,
+                ..
+            } => {
+                let scale = nanos_per_unit(unit);
+                let mut spans = vec![];
+                let mut open: Vec<(String, u64)> = vec![];
+                for (i, event) in events.iter().enumerate() {
+                    let at = to_nanos(i, event.at, scale)?;
+                    let name = frame_name(i, event.frame)?;
+                    if event.kind == "O" {
+                        open.push((name, at));
+                    } else if let Some(position) = open.iter().rposition(|(n, _)| *n == name) {
+                        let (name, start) = open.remove(position);
+                        spans.push(Span {
+                            name,
+                            start,
+                            end: at.max(start),
+                        });
+                    }
+                }
+                let end = to_nanos(events.len(), *end_value, scale)?;
+                for (name, start) in open {
+                    spans.push(Span {
+                        name,
+                        start,
+                        end: end.max(start),
+                    });
+                }
+                spans_to_traces(spans, (0, thread), &mut traces);
+            }
+            Profile::Sampled {
+                unit,
+                start_value,
+                samples,
+                weights,
+                ..
+            } => {
+                let scale = nanos_per_unit(unit);
+                let mut start = to_nanos(0, *start_value, scale)?;
+                for (i, sample) in samples.iter().enumerate() {
+                    let weight = to_nanos(i, weights.get(i).copied().unwrap_or(1.0), scale)?;
+                    let mut frames = sample
+                        .iter()
+                        .rev()
+                        .map(|f| frame_name(i, *f))
+                        .collect::<Result<Vec<_>, _>>()?
+                        .into_iter();
+                    let Some(name) = frames.next() else {
+                        continue;
+                    };
+                    traces.push(Trace {
+                        id: traces.len(),
+                        name,
+                        stack: frames.map(|name| Stackframe { name }).collect(),
+                        start,
+                        dur: weight,
+                        value: weight,
+                        pid: 0,
+                        tid: thread,
+                        metrics: Default::default(),
+                        thread: Default::default(),
+                    });
+                    start = start
+                        .checked_add(weight)
+                        .ok_or(TraceError::TimeOverflow { trace: i })?;
+                }
+            }
+        }
+        //the profile's name becomes its thread's name
+        let (Profile::Evented { name, .. } | Profile::Sampled { name, .. }) = profile;
+        if let Some(trace) = traces.get_mut(first) {
+            trace.thread.name = Some(name.clone());
+        }
+    }
+    if traces.is_empty() {
+        return Err(TraceError::Empty);
+    }
+    Ok(traces)
+}
+
+///collects sampled stacks while walking a tree, sharing one frame table
+struct SampleWriter {
+    frames: Vec<Frame>,
+    frame_index: HashMap<String, usize>,
+    samples: Vec<Vec<usize>>,
+    weights: Vec<f64>,
+    metric: DataChoices,
+}
+impl SampleWriter {
+    fn new(metric: &DataChoices) -> Self {
+        SampleWriter {
+            frames: vec![],
+            frame_index: HashMap::new(),
+            samples: vec![],
+            weights: vec![],
+            metric: metric.clone(),
+        }
+    }
+    fn frame(&mut self, name: &str) -> usize {
+        if let Some(i) = self.frame_index.get(name) {
+            return *i;
+        }
+        self.frames.push(Frame {
+            name: name.to_string(),
+        });
+        self.frame_index
+            .insert(name.to_string(), self.frames.len() - 1);
+        self.frames.len() - 1
+    }
+    ///adds a sample for the part of a node's metric that is not in its children
+    fn push(&mut self, path: &[usize], total: &TraceValues, children: &[TraceValues]) {
+        let children: f64 = children.iter().map(|c| self.metric.of(c)).sum();
+        let weight = self.metric.of(total) - children;
+        if weight > 0.0 {
+            self.samples.push(path.to_vec());
+            self.weights.push(weight);
+        }
+    }
+    fn write(self, name: &str) -> Vec<u8> {
+        let unit = match self.metric {
+            DataChoices::Duration => "nanoseconds",
+            _ => "none",
+        };
+        let file = SpeedscopeFile {
+            schema: SCHEMA.to_string(),
+            profiles: vec![Profile::Sampled {
+                name: name.to_string(),
+                unit: unit.to_string(),
+                start_value: 0.0,
+                end_value: self.weights.iter().sum(),
+                samples: self.samples,
+                weights: self.weights,
+            }],
+            shared: Shared {
+                frames: self.frames,
+            },
+            name: Some(name.to_string()),
+            exporter: Some("metaflame".to_string()),
+        };
+        serde_json::to_vec(&file).unwrap_or_default()
+    }
+}
+///writes the whole loaded profile as a sampled speedscope profile weighted by the metric
+pub fn master_to_speedscope(tree: &MasterTree, name: &str, metric: &DataChoices) -> Vec<u8> {
+    fn walk(node: &MasterNode, path: &mut Vec<usize>, writer: &mut SampleWriter) {
+        let children: Vec<TraceValues> = node.children.iter().map(MasterNode::total).collect();
+        path.push(writer.frame(&node.name));
+        writer.push(path, &node.total(), &children);
+        for child in &node.children {
+            walk(child, path, writer);
+        }
+        path.pop();
+    }
+    let mut writer = SampleWriter::new(metric);
+    for child in &tree.root.children {
+        walk(child, &mut vec![], &mut writer);
+    }
+    writer.write(name)
+}
+///writes the slice shown in the inspector as a sampled speedscope profile
+pub fn tree_to_speedscope(tree: &Tree, name: &str, metric: &DataChoices) -> Vec<u8> {
+    fn walk(node: &Node, path: &mut Vec<usize>, writer: &mut SampleWriter) {
+        let children: Vec<TraceValues> = node.children.iter().map(|c| c.values.clone()).collect();
+        path.push(writer.frame(&node.name));
+        writer.push(path, &node.values, &children);
+        for child in &node.children {
+            walk(child, path, writer);
+        }
+        path.pop();
+    }
+    let mut writer = SampleWriter::new(metric);
+    for child in &tree.root.children {
+        walk(child, &mut vec![], &mut writer);
+    }
+    writer.write(name)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///(call path, start, dur, tid) of each trace
+    fn summary(traces: &[Trace]) -> Vec<(String, u64, u64, usize)> {
+        traces
+            .iter()
+            .map(|t| {
+                let mut path: Vec<&str> = t.stack.iter().rev().map(|f| f.name.as_str()).collect();
+                path.push(&t.name);
+                (path.join(";"), t.start, t.dur, t.tid)
+            })
+            .collect()
+    }
+    ///every call path in the tree with its total duration
+    fn totals(node: &MasterNode, path: &str, out: &mut Vec<(String, u64)>) {
+        out.push((path.to_string(), node.total().dur));
+        for child in &node.children {
+            totals(child, &format!("{};{}", path, child.name), out);
+        }
+    }
+    fn tree_totals(tree: &MasterTree) -> Vec<(String, u64)> {
+        let mut out = vec![];
+        totals(&tree.root, "", &mut out);
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn evented_profiles_keep_their_timing() {
+        let input = br#"{
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "shared": {"frames": [{"name": "main"}, {"name": "work"}, {"name": "idle"}]},
+            "profiles": [{
+                "type": "evented", "name": "ui thread", "unit": "milliseconds",
+                "startValue": 0, "endValue": 12,
+                "events": [
+                    {"type": "O", "frame": 0, "at": 0},
+                    {"type": "O", "frame": 1, "at": 1},
+                    {"type": "C", "frame": 1, "at": 3},
+                    {"type": "C", "frame": 0, "at": 10},
+                    {"type": "O", "frame": 2, "at": 10}
+                ]
+            }]
+        }"#;
+        let traces = load_speedscope(input).unwrap();
+        assert_eq!(
+            summary(&traces),
+            vec![
+                ("main".to_string(), 0, 1_000_000, 0),
+                ("main;work".to_string(), 1_000_000, 2_000_000, 0),
+                ("main".to_string(), 3_000_000, 7_000_000, 0),
+                //still open at the end, so it lasts until the profile's end
+                ("idle".to_string(), 10_000_000, 2_000_000, 0),
+            ]
+        );
+        assert_eq!(traces[0].thread.name.as_deref(), Some("ui thread"));
+    }
+    #[test]
+    fn sampled_profiles_follow_each_other() {
+        let input = br#"{
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "shared": {"frames": [{"name": "main"}, {"name": "work"}]},
+            "profiles": [
+                {
+                    "type": "sampled", "name": "first", "unit": "microseconds",
+                    "startValue": 4, "endValue": 14,
+                    "samples": [[0, 1], [0], [0, 1]], "weights": [2, 3, 5]
+                },
+                {
+                    "type": "sampled", "name": "second", "unit": "none",
+                    "startValue": 0, "endValue": 1,
+                    "samples": [[1]], "weights": [7]
+                }
+            ]
+        }"#;
+        let traces = load_speedscope(input).unwrap();
+        assert_eq!(
+            summary(&traces),
+            vec![
+                ("main;work".to_string(), 4000, 2000, 0),
+                ("main".to_string(), 6000, 3000, 0),
+                ("main;work".to_string(), 9000, 5000, 0),
+                ("work".to_string(), 0, 7, 1),
+            ]
+        );
+        assert_eq!(traces[3].thread.name.as_deref(), Some("second"));
+    }
+    #[test]
+    fn missing_frames_are_errors() {
+        let input = br#"{
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "shared": {"frames": [{"name": "main"}]},
+            "profiles": [{
+                "type": "sampled", "name": "p", "unit": "none",
+                "startValue": 0, "endValue": 1, "samples": [[0, 3]], "weights": [1]
+            }]
+        }"#;
+        assert!(matches!(
+            load_speedscope(input),
+            Err(TraceError::MissingField { field: "frame", .. })
+        ));
+    }
+    #[test]
+    fn exports_load_back_with_the_same_totals() {
+        let traces = [
+            Trace {
+                dur: 40,
+                value: 40,
+                ..test_trace("main;parse;lex")
+            },
+            Trace {
+                start: 40,
+                dur: 25,
+                value: 25,
+                ..test_trace("main;parse")
+            },
+            Trace {
+                start: 65,
+                dur: 35,
+                value: 35,
+                ..test_trace("main;draw")
+            },
+            Trace {
+                start: 100,
+                ..test_trace("other")
+            },
+        ];
+        let (master, _) = grow_master_tree(&traces);
+        let exported = master_to_speedscope(&master, "profile", &DataChoices::Duration);
+        assert!(is_speedscope(&exported));
+        //only the metric written out survives, here the duration
+        let (loaded, _) = grow_master_tree(&load_speedscope(&exported).unwrap());
+        assert_eq!(tree_totals(&loaded), tree_totals(&master));
+        assert_eq!(tree_totals(&loaded)[0], (String::new(), 110));
+    }
+}
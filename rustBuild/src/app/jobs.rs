@@ -0,0 +1,260 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+
+use crate::app::data::*;
+use crate::app::derived::shade_tree;
+use crate::app::diff::diff_tree_colors;
+use crate::app::search::{darken, NamePattern};
+use crate::app::tree::{MasterTree, Tree};
+#[cfg(target_arch = "wasm32")]
+mod worker;
+/**  Jobs
+ *   Builds meshes away from the ui thread, so moving a slider on a big profile does not freeze it
+ *   Each graph has at most one job, starting another cancels the last, and the graph keeps
+ *   drawing its old mesh until the new one arrives
+ *   Natively a job gets its own thread, on the web it is sent to a web worker, see worker.rs
+*/
+///shared between a job and the app, the job checks it as it walks the tree
+#[derive(Default)]
+pub struct JobProgress {
+    cancelled: AtomicBool,
+    done: AtomicUsize,
+    total: AtomicUsize,
+}
+impl JobProgress {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+    pub fn step(&self) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+    }
+    ///how much of the job is done, None while the total is not known
+    pub fn fraction(&self) -> Option<f32> {
+        let total = self.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return None;
+        }
+        Some((self.done.load(Ordering::Relaxed) as f32 / total as f32).min(1.0))
+    }
+}
+///what a finished job hands back to the app
+pub enum JobOutput {
+    Graph(Mesh),
+    ///the inspector's tree is swapped in with its mesh, hover lookups need all three to match
+    Inspector(Tree, Mesh, PathIndex),
+}
+///how the functions of a 3d graph are faded to pick one out
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Fade {
+    Darken,
+    ///nearly see through
+    Hide,
+}
+impl Fade {
+    fn apply(self, color: [f32; 4]) -> [f32; 4] {
+        match self {
+            Fade::Darken => darken(color),
+            Fade::Hide => [color[0], color[1], color[2], 0.05],
+        }
+    }
+}
+///what a job builds, plain data so that it can be sent to a web worker
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub enum JobRequest {
+    ///a 3d graph, `fade` is a color to leave alone and what to do to every other color
+    Graph {
+        options: MeshOptions,
+        zoom: Vec<String>,
+        search: Option<NamePattern>,
+        fade: Option<([f32; 4], Fade)>,
+    },
+    ///the inspector's flamegraph of `selection`, colored by its change from `compare` if set
+    Inspector {
+        options: MeshOptions,
+        selection: InspectorSelection,
+        compare: Option<InspectorSelection>,
+        zoom: Vec<String>,
+        search: Option<NamePattern>,
+    },
+}
+impl JobRequest {
+    ///does the work, it returns None once it sees it has been cancelled
+    pub fn run(self, master: &MasterTree, progress: &JobProgress) -> Option<JobOutput> {
+        match self {
+            JobRequest::Graph {
+                options,
+                zoom,
+                search,
+                fade,
+            } => {
+                let mut mesh =
+                    get_mesh_from_tree(master, &zoom, &options, search.as_ref(), progress)?;
+                if let Some((keep, fade)) = fade {
+                    for color in mesh.colors.iter_mut().filter(|c| **c != keep) {
+                        *color = fade.apply(*color);
+                    }
+                }
+                Some(JobOutput::Graph(mesh))
+            }
+            JobRequest::Inspector {
+                options,
+                selection,
+                compare,
+                zoom,
+                search,
+            } => {
+                let mut tree = selection.build_tree(master, &options);
+                if let Some(compare) = compare {
+                    let before = compare.build_tree(master, &options);
+                    diff_tree_colors(&mut tree, &before, &options.data_metric);
+                } else if let DataChoices::Derived(expression) = &options.data_metric {
+                    shade_tree(&mut tree, expression);
+                }
+                let mut tree = tree.zoomed(&zoom);
+                if let Some(pattern) = search {
+                    pattern.dim_tree(&mut tree);
+                }
+                if progress.is_cancelled() {
+                    return None;
+                }
+                let (mesh, paths) = get_rects_from_tree(&tree, &options);
+                Some(JobOutput::Inspector(tree, mesh, paths))
+            }
+        }
+    }
+}
+///a mesh being built, dropping it cancels the build
+pub struct MeshJob {
+    progress: Arc<JobProgress>,
+    output: Receiver<JobOutput>,
+}
+impl MeshJob {
+    ///starts building what `request` asks for out of `master`
+    pub fn spawn(master: Arc<MasterTree>, request: JobRequest) -> MeshJob {
+        let progress = Arc::new(JobProgress::default());
+        let (sender, output) = channel();
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let progress = progress.clone();
+            std::thread::spawn(move || {
+                if let Some(result) = request.run(&master, &progress) {
+                    //the app may have dropped the job since, then nobody wants the result
+                    let _ = sender.send(result);
+                }
+            });
+        }
+        #[cfg(target_arch = "wasm32")]
+        worker::submit(master, request, progress.clone(), sender);
+        MeshJob { progress, output }
+    }
+    pub fn fraction(&self) -> Option<f32> {
+        self.progress.fraction()
+    }
+}
+impl Drop for MeshJob {
+    fn drop(&mut self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+///takes the output of a finished job, the slot is emptied once the job has ended either way
+pub fn take_finished(job: &mut Option<MeshJob>) -> Option<JobOutput> {
+    let received = job.as_ref()?.output.try_recv();
+    match received {
+        Ok(output) => {
+            *job = None;
+            Some(output)
+        }
+        Err(TryRecvError::Empty) => None,
+        Err(TryRecvError::Disconnected) => {
+            *job = None;
+            None
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::derived::Expression;
+    use crate::app::import::{decode_tree, encode_tree};
+    use crate::app::tree::*;
+
+    ///runs a request as given and again after the trip to a web worker and back
+    fn both_ways(master: &MasterTree, request: JobRequest) -> (JobOutput, JobOutput) {
+        let sent = serde_json::to_string(&request).unwrap();
+        let received: JobRequest = serde_json::from_str(&sent).unwrap();
+        let copy = decode_tree(&encode_tree(master)).unwrap();
+        let progress = JobProgress::default();
+        (
+            request.run(master, &progress).unwrap(),
+            received.run(&copy, &progress).unwrap(),
+        )
+    }
+    fn mesh(output: &JobOutput) -> &Mesh {
+        match output {
+            JobOutput::Graph(mesh) | JobOutput::Inspector(_, mesh, _) => mesh,
+        }
+    }
+
+    #[test]
+    fn requests_survive_the_trip_to_a_worker() {
+        let traces = [
+            Trace {
+                dur: 40,
+                value: 3,
+                ..test_trace("main;parse;lex")
+            },
+            Trace {
+                start: 40,
+                dur: 25,
+                ..test_trace("main;draw")
+            },
+            Trace {
+                start: 70,
+                ..test_trace("other")
+            },
+        ];
+        let (mut master, info) = grow_master_tree(&traces);
+        master.new_color_scheme();
+        let mut options = MeshOptions::new_3d(&info);
+        options.data_metric = DataChoices::Derived(Expression::parse("value / dur", &[]).unwrap());
+        let search = NamePattern::parse("^(lex|draw)$", true).unwrap();
+
+        let graph = JobRequest::Graph {
+            options: options.clone(),
+            zoom: vec!["main".to_string()],
+            search: search.clone(),
+            fade: Some(([1.0; 4], Fade::Hide)),
+        };
+        let (here, there) = both_ways(&master, graph);
+        assert!(!mesh(&here).verts.is_empty());
+        assert_eq!(mesh(&here).verts, mesh(&there).verts);
+        assert_eq!(mesh(&here).colors, mesh(&there).colors);
+
+        let inspector = JobRequest::Inspector {
+            options: options.clone(),
+            selection: InspectorSelection::of_division(&options, 0),
+            compare: Some(InspectorSelection::of_division(&options, 1)),
+            zoom: vec![],
+            search,
+        };
+        let (here, there) = both_ways(&master, inspector);
+        assert_eq!(mesh(&here).verts, mesh(&there).verts);
+        assert_eq!(mesh(&here).colors, mesh(&there).colors);
+        let (
+            JobOutput::Inspector(here_tree, _, here_paths),
+            JobOutput::Inspector(there_tree, _, there_paths),
+        ) = (here, there)
+        else {
+            panic!("inspector requests build inspector trees");
+        };
+        assert_eq!(here_paths.rects, there_paths.rects);
+        assert_eq!(
+            here_tree.root.children.len(),
+            there_tree.root.children.len()
+        );
+    }
+}
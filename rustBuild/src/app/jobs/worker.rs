@@ -0,0 +1,297 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use js_sys::{Array, Float32Array, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, Worker};
+
+use super::{JobOutput, JobProgress, JobRequest};
+use crate::app::data::{Mesh, PathIndex};
+use crate::app::import::{decode_tree, encode_tree};
+use crate::app::tree::{MasterTree, Tree};
+/**  Worker
+ *   Runs mesh jobs on the web in a web worker, which loads a second copy of this module and
+ *   shares no memory with the page. The page sends it
+ *       ["tree", bytes]                 the master tree, whenever the job's is not the one it has
+ *       ["job", json]                   a JobRequest, once the last job has been answered
+ *   and the worker says it has loaded, then answers every job
+ *       ["ready"]
+ *       ["done", json, verts, colors]   json holds the inspector's tree and paths, or null
+ *       ["failed", message]
+ *   A job cancelled while waiting is never sent, one cancelled while running is left to finish
+ *   and its mesh dropped. Jobs run on the page as before when the worker can not be started
+*/
+const WORKER_SCRIPT: &str = r#"
+import init, { mesh_worker } from "GLUE";
+const early = [];
+self.onmessage = (event) => early.push(event);
+await init();
+mesh_worker();
+for (const event of early) self.onmessage(event);
+"#;
+
+///a job waiting for the worker
+struct Queued {
+    master: Arc<MasterTree>,
+    request: JobRequest,
+    progress: Arc<JobProgress>,
+    sender: Sender<JobOutput>,
+}
+impl Queued {
+    fn run_here(self) {
+        if let Some(result) = self.request.run(&self.master, &self.progress) {
+            let _ = self.sender.send(result);
+        }
+    }
+}
+///the page's end of the worker
+struct Bridge {
+    worker: Worker,
+    ///set once the worker has loaded the module
+    loaded: bool,
+    ///the tree the worker has, holding on to it makes any change to the app's tree a new Arc
+    sent: Option<Arc<MasterTree>>,
+    running: Option<Queued>,
+    queue: VecDeque<Queued>,
+}
+enum WorkerState {
+    Unstarted,
+    Started(Box<Bridge>),
+    ///the worker could not be started or has crashed, jobs run on the page
+    Broken,
+}
+thread_local! {
+    static STATE: RefCell<WorkerState> = const { RefCell::new(WorkerState::Unstarted) };
+    ///the worker's copy of the tree
+    static TREE: RefCell<Result<MasterTree, String>> =
+        RefCell::new(Err("no tree has been sent".to_string()));
+}
+
+///queues a job for the worker, starting the worker for the first job
+pub fn submit(
+    master: Arc<MasterTree>,
+    request: JobRequest,
+    progress: Arc<JobProgress>,
+    sender: Sender<JobOutput>,
+) {
+    let job = Queued {
+        master,
+        request,
+        progress,
+        sender,
+    };
+    let job = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if let WorkerState::Unstarted = *state {
+            *state = start_worker().map_or(WorkerState::Broken, |bridge| {
+                WorkerState::Started(Box::new(bridge))
+            });
+        }
+        let WorkerState::Started(bridge) = &mut *state else {
+            return Some(job);
+        };
+        bridge.queue.push_back(job);
+        bridge.dispatch();
+        None
+    });
+    if let Some(job) = job {
+        job.run_here();
+    }
+}
+fn start_worker() -> Option<Bridge> {
+    let document = web_sys::window()?.document()?;
+    //index.html loads the module's glue from the base url, trunk names it after the crate
+    let base = document.base_uri().ok()??;
+    let glue = concat!(env!("CARGO_PKG_NAME"), ".js");
+    let glue = web_sys::Url::new_with_base(glue, &base).ok()?.href();
+    let script = JsValue::from(WORKER_SCRIPT.replace("GLUE", &glue));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type("text/javascript");
+    let blob =
+        web_sys::Blob::new_with_str_sequence_and_options(&Array::of1(&script), &options).ok()?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).ok()?;
+    let options = web_sys::WorkerOptions::new();
+    options.set_type(web_sys::WorkerType::Module);
+    let worker = Worker::new_with_options(&url, &options).ok()?;
+
+    //the worker lasts as long as the page, so its handlers are never freed
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(on_answer);
+    worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+    let on_error = Closure::<dyn FnMut(web_sys::Event)>::new(on_error);
+    worker.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+    on_error.forget();
+    Some(Bridge {
+        worker,
+        loaded: false,
+        sent: None,
+        running: None,
+        queue: VecDeque::new(),
+    })
+}
+impl Bridge {
+    ///sends the next job that is still wanted, if the worker is free
+    fn dispatch(&mut self) {
+        if self.running.is_some() {
+            return;
+        }
+        while let Some(job) = self.queue.pop_front() {
+            if job.progress.is_cancelled() {
+                continue;
+            }
+            if self.send(&job).is_none() {
+                job.run_here();
+                continue;
+            }
+            self.running = Some(job);
+            return;
+        }
+    }
+    fn send(&mut self, job: &Queued) -> Option<()> {
+        let request = serde_json::to_string(&job.request).ok()?;
+        let has_tree = self
+            .sent
+            .as_ref()
+            .map_or(false, |sent| Arc::ptr_eq(sent, &job.master));
+        if !has_tree {
+            let bytes = Uint8Array::from(&encode_tree(&job.master)[..]);
+            let message = Array::of2(&"tree".into(), &bytes);
+            self.worker
+                .post_message_with_transfer(&message, &Array::of1(&bytes.buffer()))
+                .ok()?;
+            self.sent = Some(job.master.clone());
+        }
+        let message = Array::of2(&"job".into(), &request.into());
+        self.worker.post_message(&message).ok()
+    }
+}
+fn on_answer(event: MessageEvent) {
+    let answer: Array = event.data().unchecked_into();
+    STATE.with(|state| {
+        let WorkerState::Started(bridge) = &mut *state.borrow_mut() else {
+            return;
+        };
+        if answer.get(0).as_string().as_deref() == Some("ready") {
+            bridge.loaded = true;
+            return;
+        }
+        let job = bridge.running.take();
+        match read_answer(&answer) {
+            Ok(output) => {
+                if let Some(job) = job {
+                    //the app may have dropped the job since, then nobody wants the result
+                    let _ = job.sender.send(output);
+                }
+            }
+            Err(message) => {
+                web_sys::console::error_1(&format!("mesh worker: {}", message).into());
+            }
+        }
+        bridge.dispatch();
+    });
+}
+fn on_error(_: web_sys::Event) {
+    let jobs = STATE.with(|state| {
+        let state = std::mem::replace(&mut *state.borrow_mut(), WorkerState::Broken);
+        let WorkerState::Started(bridge) = state else {
+            return VecDeque::new();
+        };
+        bridge.worker.terminate();
+        let Bridge {
+            worker: _,
+            loaded,
+            sent: _,
+            running,
+            queue: mut jobs,
+        } = *bridge;
+        //a job that crashed the worker would crash the page too, only retry one it never ran
+        if let (false, Some(job)) = (loaded, running) {
+            jobs.push_front(job);
+        }
+        jobs
+    });
+    for job in jobs {
+        job.run_here();
+    }
+}
+fn read_answer(answer: &Array) -> Result<JobOutput, String> {
+    if answer.get(0).as_string().as_deref() != Some("done") {
+        return Err(answer.get(1).as_string().unwrap_or_default());
+    }
+    let verts = Float32Array::new(&answer.get(2)).to_vec();
+    let colors = Float32Array::new(&answer.get(3)).to_vec();
+    let mesh = Mesh {
+        verts: verts.chunks_exact(3).map(|v| [v[0], v[1], v[2]]).collect(),
+        colors: colors
+            .chunks_exact(4)
+            .map(|c| [c[0], c[1], c[2], c[3]])
+            .collect(),
+    };
+    match answer.get(1).as_string() {
+        None => Ok(JobOutput::Graph(mesh)),
+        Some(json) => {
+            let (tree, paths): (Tree, PathIndex) =
+                serde_json::from_str(&json).map_err(|e| e.to_string())?;
+            Ok(JobOutput::Inspector(tree, mesh, paths))
+        }
+    }
+}
+
+///started by the worker script once the module has loaded, from then on it answers the page
+#[wasm_bindgen]
+pub fn mesh_worker() {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(on_request);
+    scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+    let _ = scope.post_message(&Array::of1(&"ready".into()));
+}
+fn on_request(event: MessageEvent) {
+    let message: Array = event.data().unchecked_into();
+    match message.get(0).as_string().as_deref() {
+        Some("tree") => {
+            let tree = decode_tree(&Uint8Array::new(&message.get(1)).to_vec());
+            TREE.with(|held| *held.borrow_mut() = tree.map_err(|e| e.to_string()));
+        }
+        Some("job") => {
+            let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+            let posted = match run_request(&message.get(1)).and_then(write_answer) {
+                Ok((answer, transfer)) => scope.post_message_with_transfer(&answer, &transfer),
+                Err(message) => scope.post_message(&Array::of2(&"failed".into(), &message.into())),
+            };
+            if let Err(error) = posted {
+                web_sys::console::error_1(&error);
+            }
+        }
+        _ => {}
+    }
+}
+fn run_request(request: &JsValue) -> Result<JobOutput, String> {
+    let request = request.as_string().unwrap_or_default();
+    let request: JobRequest = serde_json::from_str(&request).map_err(|e| e.to_string())?;
+    TREE.with(|tree| match &*tree.borrow() {
+        //nothing cancels a job once it is in the worker
+        Ok(tree) => request
+            .run(tree, &JobProgress::default())
+            .ok_or_else(|| "the job was cancelled".to_string()),
+        Err(message) => Err(message.clone()),
+    })
+}
+///the answer to a finished job and the buffers moved along with it
+fn write_answer(output: JobOutput) -> Result<(Array, Array), String> {
+    let (json, mesh) = match output {
+        JobOutput::Graph(mesh) => (JsValue::NULL, mesh),
+        JobOutput::Inspector(tree, mesh, paths) => {
+            let json = serde_json::to_string(&(tree, paths)).map_err(|e| e.to_string())?;
+            (json.into(), mesh)
+        }
+    };
+    let verts = Float32Array::from(&mesh.verts.concat()[..]);
+    let colors = Float32Array::from(&mesh.colors.concat()[..]);
+    let transfer = Array::of2(&verts.buffer(), &colors.buffer());
+    let answer = Array::of4(&"done".into(), &json, &verts, &colors);
+    Ok((answer, transfer))
+}
@@ -0,0 +1,411 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use super::{App, ClearOptions};
+use crate::app::data::*;
+use crate::app::diff::*;
+use crate::app::import::*;
+use crate::app::tree::*;
+
+use egui::mutex::Mutex;
+/**  Loader
+ *   Reads trace files picked from a file dialog or dropped onto the window
+ *   and swaps them in as the loaded profile
+*/
+///a file that has been picked but not yet loaded into the app
+#[derive(Clone)]
+pub struct PickedFile {
+    pub name: String,
+    ///None natively, where the file is read from the path in `name` while it is imported
+    pub bytes: Option<Vec<u8>>,
+}
+impl PickedFile {
+    ///the start of the file, enough to tell its format
+    fn head(&self) -> Cow<'_, [u8]> {
+        match &self.bytes {
+            Some(bytes) => Cow::Borrowed(&bytes[..bytes.len().min(HEAD_LEN)]),
+            None => {
+                use std::io::Read;
+                let mut head = vec![];
+                if let Ok(file) = std::fs::File::open(&self.name) {
+                    let _ = file.take(HEAD_LEN as u64).read_to_end(&mut head);
+                }
+                Cow::Owned(head)
+            }
+        }
+    }
+    ///the whole file, for the formats that are not streamed
+    fn contents(&self) -> Result<Cow<'_, [u8]>, TraceError> {
+        match &self.bytes {
+            Some(bytes) => Ok(Cow::Borrowed(bytes)),
+            None => Ok(Cow::Owned(std::fs::read(&self.name)?)),
+        }
+    }
+    fn import(&self, options: &ImportOptions) -> Result<(MasterTree, TreeInfo), TraceError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.bytes.is_none() {
+            return import_file(std::path::Path::new(&self.name), options);
+        }
+        import_tree(&self.name, &self.contents()?, options)
+    }
+}
+///the file dialog is async on the web, so picked files are handed back through a shared slot
+#[derive(Default)]
+pub struct FileLoader {
+    pub picked: Arc<Mutex<Vec<PickedFile>>>,
+    pub file_name: Option<String>,
+    pub error: Option<TraceError>,
+    pub options: ImportOptions,
+    ///sample types of the loaded file, kept with the file so it can be re-read with another one
+    pub sample_types: Vec<String>,
+    pub reload: Option<PickedFile>,
+    ///a baseline picked to compare the loaded profile against
+    pub baseline_picked: Arc<Mutex<Vec<PickedFile>>>,
+    pub baseline_name: Option<String>,
+    ///call paths of the baseline that the loaded profile does not have
+    pub baseline_only: usize,
+    pub show_diff: bool,
+    ///keep a processed copy next to each trace file that is opened
+    #[cfg(not(target_arch = "wasm32"))]
+    pub cache: bool,
+}
+impl App {
+    ///button that opens a file dialog for picking trace files
+    pub fn open_file_button(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Open File").clicked() {
+            self.pick_files(self.file_loader.picked.clone());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.checkbox(&mut self.file_loader.cache, "Cache")
+            .on_hover_text(format!(
+                "keeps a .{} copy next to each trace file, so it opens faster next time",
+                CACHE_EXTENSION
+            ));
+        if let Some(name) = &self.file_loader.file_name {
+            ui.label(format!(
+                "{}    {} traces, {} threads in {} processes, depth {}",
+                name,
+                self.data_info.nodes,
+                self.data_info.num_threads,
+                self.data_info.threads.num_processes(),
+                self.data_info.max_depth
+            ));
+        }
+        if !self.file_loader.sample_types.is_empty() {
+            self.sample_type_dropdown(ui);
+        }
+        if self.file_loader.file_name.is_some() {
+            if ui.button("Export Speedscope").clicked() {
+                let bytes = master_to_speedscope(
+                    &self.master_graph,
+                    "profile",
+                    &self.ui_state.inspector_options.data_metric,
+                );
+                self.save_file("profile.speedscope.json", &bytes);
+            }
+            if ui.button("Export Slice").clicked() {
+                let bytes = tree_to_speedscope(
+                    &self.inspector_graph,
+                    "slice",
+                    &self.ui_state.inspector_options.data_metric,
+                );
+                self.save_file("slice.speedscope.json", &bytes);
+            }
+            //a saved cache opens without parsing, with any colors changed since loading
+            if ui.button("Export Cache").clicked() {
+                let bytes =
+                    encode_cache(&self.master_graph, &self.data_info, CacheSource::default());
+                self.save_file(&format!("profile.{}", CACHE_EXTENSION), &bytes);
+            }
+        }
+    }
+    ///opens a file dialog, the files picked are pushed into `picked`
+    fn pick_files(&mut self, picked: Arc<Mutex<Vec<PickedFile>>>) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let paths = rfd::FileDialog::new()
+                .add_filter("trace", TRACE_EXTENSIONS)
+                .pick_files()
+                .unwrap_or_default();
+            picked
+                .lock()
+                .extend(paths.into_iter().map(|path| PickedFile {
+                    name: path.display().to_string(),
+                    bytes: None,
+                }));
+        }
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(async move {
+            let files = rfd::AsyncFileDialog::new()
+                .add_filter("trace", TRACE_EXTENSIONS)
+                .pick_files()
+                .await
+                .unwrap_or_default();
+            let mut read = vec![];
+            for file in files {
+                read.push(PickedFile {
+                    name: file.file_name(),
+                    bytes: Some(file.read().await),
+                });
+            }
+            picked.lock().append(&mut read);
+        });
+    }
+    ///buttons for comparing the loaded profile against a baseline, such as an older build
+    pub fn baseline_buttons(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
+        if self.file_loader.file_name.is_none() {
+            return;
+        }
+        if ui.button("Open Baseline").clicked() {
+            self.pick_files(self.file_loader.baseline_picked.clone());
+        }
+        let Some(name) = &self.file_loader.baseline_name else {
+            return;
+        };
+        ui.label(format!(
+            "against {}, {} call paths only in the baseline",
+            name, self.file_loader.baseline_only
+        ));
+        let shown = self.file_loader.show_diff;
+        ui.checkbox(&mut self.file_loader.show_diff, "Diff");
+        if shown != self.file_loader.show_diff {
+            self.recolor();
+            self.regen_all(frame);
+        }
+        if ui.button("Clear Baseline").clicked() {
+            self.clear_baseline();
+            self.recolor();
+            self.regen_all(frame);
+        }
+    }
+    ///colors the tree by its scheme, or by its change from the baseline while the diff is shown
+    pub fn recolor(&mut self) {
+        let diff = self.file_loader.show_diff && self.file_loader.baseline_name.is_some();
+        let master = Arc::make_mut(&mut self.master_graph);
+        if diff {
+            diff_colors(master, &self.graph_left.mesh_options.data_metric);
+        } else if master.color_scheme == ColorScheme::Trend {
+            trend_colors(master, &self.graph_left.mesh_options);
+        } else {
+            master.new_color_scheme();
+        }
+    }
+    ///the diff and trend colors are worked out with the left graph's metric and time slices
+    pub fn colors_follow_left(&self) -> bool {
+        self.file_loader.show_diff || self.master_graph.color_scheme == ColorScheme::Trend
+    }
+    fn clear_baseline(&mut self) {
+        if self.file_loader.baseline_name.take().is_some() {
+            clear_baseline(Arc::make_mut(&mut self.master_graph));
+        }
+        self.file_loader.baseline_only = 0;
+        self.file_loader.show_diff = false;
+    }
+    ///merges a baseline profile into the loaded one and shows the difference
+    fn load_baseline(&mut self, file: PickedFile, frame: &mut eframe::Frame) {
+        let baseline = match self.import_with_cache(&file) {
+            Ok((baseline, _)) => baseline,
+            Err(e) => {
+                self.file_loader.error = Some(e);
+                return;
+            }
+        };
+        let master = Arc::make_mut(&mut self.master_graph);
+        self.file_loader.baseline_only = merge_baseline(master, &baseline);
+        self.file_loader.baseline_name = Some(file.name);
+        self.file_loader.show_diff = true;
+        self.file_loader.error = None;
+        self.recolor();
+        self.regen_all(frame);
+    }
+    ///asks where to save a file natively, on the web the browser downloads it
+    pub fn save_file(&mut self, name: &str, bytes: &[u8]) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = rfd::FileDialog::new().set_file_name(name).save_file();
+            if let Some(path) = path {
+                if let Err(e) = std::fs::write(path, bytes) {
+                    self.file_loader.error = Some(TraceError::Io(e.to_string()));
+                }
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        if download(name, bytes).is_none() {
+            self.file_loader.error = Some(TraceError::Io("download failed".to_string()));
+        }
+    }
+    ///picks which sample type becomes the value for profiles that record several
+    pub fn sample_type_dropdown(&mut self, ui: &mut egui::Ui) {
+        let loader = &mut self.file_loader;
+        let past_option = loader.options.sample_type.clone();
+        let mut selected = past_option.clone();
+        ui.label("Sample Type");
+        egui::containers::ComboBox::from_id_source("sample_type")
+            .selected_text(selected.clone().unwrap_or_else(|| "Default".to_string()))
+            .show_ui(ui, |ui| {
+                for name in &loader.sample_types {
+                    ui.selectable_value(&mut selected, Some(name.clone()), name);
+                }
+            });
+        if past_option != selected {
+            loader.options.sample_type = selected;
+            if let Some(file) = loader.reload.clone() {
+                loader.picked.lock().push(file);
+            }
+        }
+    }
+    ///checks for files from the file dialog or dropped onto the window and loads them
+    pub fn receive_files(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let mut files = std::mem::take(&mut *self.file_loader.picked.lock());
+        for dropped in ctx.input().raw.dropped_files.iter() {
+            if let Some(bytes) = &dropped.bytes {
+                files.push(PickedFile {
+                    name: dropped.name.clone(),
+                    bytes: Some(bytes.to_vec()),
+                });
+            } else if let Some(path) = &dropped.path {
+                files.push(PickedFile {
+                    name: path.display().to_string(),
+                    bytes: None,
+                });
+            }
+        }
+        if !files.is_empty() {
+            self.load_profile(files, frame);
+        }
+        let baseline = self.file_loader.baseline_picked.lock().pop();
+        if let Some(file) = baseline {
+            self.load_baseline(file, frame);
+        }
+    }
+    ///shows why the last file failed to load, the previous profile stays loaded
+    pub fn error_panel(&mut self, ctx: &egui::Context) {
+        let Some(error) = &self.file_loader.error else {
+            return;
+        };
+        let mut dismissed = false;
+        egui::TopBottomPanel::top("error_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(egui::Color32::RED, error.to_string());
+                dismissed = ui.button("Dismiss").clicked();
+            });
+        });
+        if dismissed {
+            self.file_loader.error = None;
+        }
+    }
+    ///imports through the file's cache when caching is turned on
+    fn import_with_cache(&self, file: &PickedFile) -> Result<(MasterTree, TreeInfo), TraceError> {
+        let options = &self.file_loader.options;
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.file_loader.cache && file.bytes.is_none() && !is_cache(&file.head()) {
+            return import_file_cached(std::path::Path::new(&file.name), options);
+        }
+        file.import(options)
+    }
+    ///replaces the loaded profile and rebuilds every graph from it
+    pub fn load_profile(&mut self, mut files: Vec<PickedFile>, frame: &mut eframe::Frame) {
+        //several folded files are read as snapshots in name order, with numbers in the names
+        //compared by value, otherwise the last file is kept
+        let snapshots = files.len() > 1
+            && files
+                .iter()
+                .all(|f| detect_format(&f.name, &f.head()) == TraceFormat::Folded);
+        let (name, result) = if snapshots {
+            files.sort_by(|a, b| natural_cmp(&a.name, &b.name));
+            let contents: Result<Vec<_>, _> = files.iter().map(PickedFile::contents).collect();
+            let contents = match contents {
+                Ok(contents) => contents,
+                Err(e) => {
+                    self.file_loader.error = Some(e);
+                    return;
+                }
+            };
+            let inputs: Vec<&[u8]> = contents.iter().map(|c| c.as_ref()).collect();
+            let name = format!(
+                "{} .. {} ({} snapshots)",
+                files[0].name,
+                files[files.len() - 1].name,
+                files.len()
+            );
+            let result = import_snapshots(&inputs);
+            if result.is_ok() {
+                self.file_loader.sample_types.clear();
+                self.file_loader.reload = None;
+            }
+            (name, result)
+        } else {
+            let Some(file) = files.pop() else {
+                return;
+            };
+            let result = self.import_with_cache(&file);
+            if let Ok((_, info)) = &result {
+                self.file_loader.sample_types = info.sample_types.clone();
+                self.file_loader.reload = if self.file_loader.sample_types.is_empty() {
+                    None
+                } else {
+                    Some(file.clone())
+                };
+            }
+            (file.name, result)
+        };
+        let (mut master, info) = match result {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                self.file_loader.error = Some(e);
+                return;
+            }
+        };
+        //cached trees keep their colors when they were saved with the scheme in use
+        if master.color_scheme != self.master_graph.color_scheme
+            || master.color_salt != self.master_graph.color_salt
+        {
+            master.color_scheme = self.master_graph.color_scheme;
+            master.color_salt = self.master_graph.color_salt;
+            master.new_color_scheme();
+        }
+
+        self.master_graph = Arc::new(master);
+        self.file_loader.baseline_name = None;
+        self.file_loader.baseline_only = 0;
+        self.file_loader.show_diff = false;
+        self.graph_left.mesh_options = MeshOptions::new_3d(&info);
+        self.graph_right.mesh_options = MeshOptions::new_3d(&info);
+        if snapshots {
+            self.graph_left.mesh_options.num_graphs = files.len();
+            self.graph_right.mesh_options.num_graphs = files.len();
+        }
+        self.ui_state.inspector_options = MeshOptions::new_2d(&info);
+        self.ui_state.inspector_selection =
+            InspectorSelection::Time(self.ui_state.inspector_options.time_range.clone());
+        self.ui_state.inspector_compare = None;
+        self.ui_state.pinned = None;
+        self.ui_state.unpinned = None;
+        self.ui_state.zoom.clear();
+        self.ui_state.modify_options.open = false;
+        self.ui_state.modify_options.highlighting_node = false;
+        self.data_info = info;
+        self.file_loader.file_name = Some(name);
+        self.file_loader.error = None;
+
+        if self.colors_follow_left() {
+            self.recolor();
+        }
+        self.clear_options = ClearOptions::NeedsFirstClear;
+        self.regen_all(frame);
+    }
+}
+///hands bytes to the browser as a file download
+#[cfg(target_arch = "wasm32")]
+fn download(name: &str, bytes: &[u8]) -> Option<()> {
+    use wasm_bindgen::JsCast;
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts).ok()?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).ok()?;
+    let document = web_sys::window()?.document()?;
+    let anchor: web_sys::HtmlAnchorElement = document.create_element("a").ok()?.dyn_into().ok()?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+    web_sys::Url::revoke_object_url(&url).ok()
+}
@@ -0,0 +1,150 @@
+use crate::app::data::DataChoices;
+use crate::app::tree::*;
+/**  Search
+ *   Finds functions by name, either containing the text typed or matching it as a regex
+ *   Matches keep their colors in every graph while the rest are darkened, like "Darken Others"
+*/
+///how much the colors of functions that do not match are darkened
+const DIM: f32 = 0.1;
+///a name to look for, parsed from the search box
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub enum NamePattern {
+    Substring(String),
+    Regex(#[serde(with = "regex_text")] regex_lite::Regex),
+}
+///regexes are sent to the web worker as their text and compiled again there
+mod regex_text {
+    use serde::{Deserialize, Deserializer, Serializer};
+    pub fn serialize<S: Serializer>(
+        regex: &regex_lite::Regex,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(regex.as_str())
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<regex_lite::Regex, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        regex_lite::Regex::new(&text).map_err(serde::de::Error::custom)
+    }
+}
+impl NamePattern {
+    ///None when there is nothing to look for, an error when the regex does not parse
+    pub fn parse(text: &str, regex: bool) -> Result<Option<NamePattern>, String> {
+        if text.is_empty() {
+            return Ok(None);
+        }
+        if !regex {
+            return Ok(Some(NamePattern::Substring(text.to_string())));
+        }
+        regex_lite::Regex::new(text)
+            .map(|regex| Some(NamePattern::Regex(regex)))
+            .map_err(|e| e.to_string())
+    }
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Substring(text) => name.contains(text.as_str()),
+            NamePattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+    ///darkens every function of the inspector's flamegraph that does not match
+    pub fn dim_tree(&self, tree: &mut Tree) {
+        fn dim(node: &mut Node, pattern: &NamePattern) {
+            if !pattern.matches(&node.name) {
+                node.color = node.color.map(darken);
+            }
+            for child in &mut node.children {
+                dim(child, pattern);
+            }
+        }
+        for child in &mut tree.root.children {
+            dim(child, self);
+        }
+    }
+    ///the share of the slice's `metric` spent in matching functions shown in the flamegraph,
+    ///measured the way bars are sized, a match called from inside another is already counted
+    pub fn matched_share(&self, tree: &Tree, metric: &DataChoices) -> f64 {
+        let metric = &metric.sized_by();
+        fn matched(node: &Node, pattern: &NamePattern, metric: &DataChoices) -> f64 {
+            if pattern.matches(&node.name) {
+                return metric.of(&node.values);
+            }
+            node.children
+                .iter()
+                .map(|child| matched(child, pattern, metric))
+                .sum()
+        }
+        let total = metric.of(tree.slice_total());
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let amount: f64 = tree
+            .root
+            .children
+            .iter()
+            .map(|child| matched(child, self, metric))
+            .sum();
+        amount / total
+    }
+}
+///the color a function the search does not match is drawn in
+pub fn darken(color: [f32; 4]) -> [f32; 4] {
+    [color[0] * DIM, color[1] * DIM, color[2] * DIM, color[3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::data::Attribution;
+
+    fn pattern(text: &str, regex: bool) -> NamePattern {
+        NamePattern::parse(text, regex).unwrap().unwrap()
+    }
+    ///the flamegraph of calls of each path lasting 10, one after the other
+    fn flamegraph(paths: &[&str]) -> Tree {
+        let traces: Vec<Trace> = paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| Trace {
+                start: i as u64 * 10,
+                ..test_trace(path)
+            })
+            .collect();
+        let (master, info) = grow_master_tree(&traces);
+        build_time_tree(&master, info.start..info.end, Attribution::Proportional)
+    }
+
+    #[test]
+    fn invalid_regexes_are_errors() {
+        assert!(NamePattern::parse("parse(", true).is_err());
+        //the same text is fine to look for as it is
+        assert!(matches!(
+            NamePattern::parse("parse(", false),
+            Ok(Some(NamePattern::Substring(_)))
+        ));
+        assert!(matches!(NamePattern::parse("", true), Ok(None)));
+    }
+    #[test]
+    fn substrings_are_literal_and_regexes_are_not() {
+        let substring = pattern("parse", false);
+        assert!(substring.matches("parse_args") && substring.matches("reparse"));
+        let regex = pattern("^parse", true);
+        assert!(regex.matches("parse_args") && !regex.matches("reparse"));
+        assert!(!pattern("^parse", false).matches("parse_args"));
+        assert!(pattern("a.b", false).matches("a.b"));
+        assert!(!pattern("a.b", false).matches("axb"));
+        assert!(pattern("a.b", true).matches("axb"));
+    }
+    #[test]
+    fn nested_matches_are_counted_once() {
+        let tree = flamegraph(&["main;parse;parse", "main;draw", "main;parse"]);
+        let parse = pattern("parse", false);
+        let share = parse.matched_share(&tree, &DataChoices::Duration);
+        assert!((share - 2.0 / 3.0).abs() < 1e-9);
+        let both = pattern("parse|draw", true);
+        assert!((both.matched_share(&tree, &DataChoices::Duration) - 1.0).abs() < 1e-9);
+        //main holds every match, matching it as well changes nothing
+        let all = pattern("main|parse", true);
+        assert!((all.matched_share(&tree, &DataChoices::Duration) - 1.0).abs() < 1e-9);
+    }
+}
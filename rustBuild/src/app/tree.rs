@@ -1,5 +1,7 @@
+use crate::app::data::Attribution;
+use crate::app::diff::diverging_color;
 use std::{
-    collections::hash_map::DefaultHasher,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
     fmt::Debug,
     hash::{Hash, Hasher},
     ops::Range,
@@ -10,14 +12,18 @@ pub enum ColorScheme {
     Greyscale,
     Flame,
     Ice,
+    ///red or blue by whether a node's share rises or falls across the time slices
+    Trend,
 }
 const DEFAULT_COLOR: ColorScheme = ColorScheme::Rainbow;
-#[derive(Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub struct TraceValues {
     pub start: u64,
     pub dur: u64,
     pub value: u64,
     pub thread: usize,
+    ///named metrics, indexed the same as `TreeInfo.metrics`, missing entries count as 0
+    pub metrics: Vec<u64>,
 }
 pub fn trace_zero() -> TraceValues {
     TraceValues {
@@ -25,21 +31,39 @@ pub fn trace_zero() -> TraceValues {
         dur: 0,
         value: 0,
         thread: 0,
+        metrics: vec![],
     }
 }
-#[derive(Debug, Clone)]
+impl TraceValues {
+    ///the named metric at `index`, 0 when it was not recorded
+    pub fn metric(&self, index: usize) -> u64 {
+        self.metrics.get(index).copied().unwrap_or(0)
+    }
+    ///adds the duration, value and metrics of `other` into these values
+    pub fn add(&mut self, other: &TraceValues) {
+        self.dur += other.dur;
+        self.value += other.value;
+        if self.metrics.len() < other.metrics.len() {
+            self.metrics.resize(other.metrics.len(), 0);
+        }
+        for (total, metric) in self.metrics.iter_mut().zip(&other.metrics) {
+            *total += metric;
+        }
+    }
+}
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub struct Tree {
     pub root: Node,
-    pub time_range: Range<u64>,
+    ///totals of the whole slice once it is zoomed into a node, whose totals the root takes
+    pub zoomed_from: Option<Box<TraceValues>>,
 }
-#[derive(Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub struct Node {
     /// function name:
     pub name: String,
     pub values: TraceValues,
     pub children: Vec<Node>,
     pub color: Option<[f32; 4]>,
-    pub offsets: TraceValues,
 }
 ///This is a flamegraph, specifically one with all traces from the input
 #[derive(Debug, Clone)]
@@ -55,124 +79,318 @@ pub struct MasterNode {
     pub values: Vec<TraceValues>,
     pub children: Vec<MasterNode>,
     pub color: Option<[f32; 4]>,
+    pub index: TimeIndex,
+    ///totals of the same call path in a baseline profile, while one is being compared against
+    pub baseline: Option<TraceValues>,
+}
+///running totals over a node's events, which `build_index` sorts by start, so the time spent
+///in any range takes two binary searches instead of a pass over every event
+#[derive(Debug, Clone, Default)]
+pub struct TimeIndex {
+    ///sum of the starts of the events before each position
+    start_sums: Vec<u128>,
+    ///value and metrics of the events before each position, in start order
+    by_start: Totals,
+    ///the last instant each event covers, its start when it has no duration, in order
+    ///an event has ended by `time` exactly when its last instant is before it
+    lasts: Vec<u64>,
+    ///sum of the ends of the events before each position, in `lasts` order
+    end_sums: Vec<u128>,
+    ///value and metrics of the events before each position, in `lasts` order
+    by_last: Totals,
+    ///segment tree of the largest end under each branch, over the events in start order
+    max_ends: Vec<u64>,
 }
-pub fn build_time_tree(node: &MasterTree, range: Range<u64>) -> Tree {
-    let Some(mut root )= build_time_node_subtree(&node.root, range.clone()) else {
-        return Tree{ root: Node{ name: "root".to_string(), values: trace_zero(), children: vec![], color: None, offsets: trace_zero() }, time_range: range };
+///running sums of value and each metric, one column per amount
+#[derive(Debug, Clone, Default)]
+struct Totals {
+    value: Vec<u64>,
+    metrics: Vec<Vec<u64>>,
+}
+impl Totals {
+    fn new<'a>(values: impl Iterator<Item = &'a TraceValues>, num_metrics: usize) -> Self {
+        let mut totals = Totals {
+            value: vec![0],
+            metrics: vec![vec![0]; num_metrics],
+        };
+        for value in values {
+            totals
+                .value
+                .push(totals.value.last().unwrap() + value.value);
+            for (i, column) in totals.metrics.iter_mut().enumerate() {
+                column.push(column.last().unwrap() + value.metric(i));
+            }
+        }
+        totals
+    }
+    ///value then each metric, summed over the events in `range`
+    fn between(&self, range: Range<usize>) -> Vec<f64> {
+        let mut amounts = vec![(self.value[range.end] - self.value[range.start]) as f64];
+        for column in &self.metrics {
+            amounts.push((column[range.end] - column[range.start]) as f64);
+        }
+        amounts
+    }
+}
+impl Tree {
+    ///re-roots the flamegraph on the node at the end of `path`, so it spans the whole width
+    ///the tree is left empty when this slice does not have that call path
+    pub fn zoomed(self, path: &[String]) -> Tree {
+        if path.is_empty() {
+            return self;
+        }
+        let slice = Box::new(self.slice_total().clone());
+        let mut node = self.root;
+        for name in path {
+            let Some(i) = node.children.iter().position(|c| c.name == *name) else {
+                return Tree {
+                    root: Node {
+                        name: "root".to_string(),
+                        values: trace_zero(),
+                        children: vec![],
+                        color: None,
+                    },
+                    zoomed_from: Some(slice),
+                };
+            };
+            node = node.children.swap_remove(i);
+        }
+        Tree {
+            root: Node {
+                name: "root".to_string(),
+                values: node.values.clone(),
+                children: vec![node],
+                color: None,
+            },
+            zoomed_from: Some(slice),
+        }
+    }
+    ///totals of the whole slice, which percentages are of even while zoomed in
+    pub fn slice_total(&self) -> &TraceValues {
+        self.zoomed_from.as_deref().unwrap_or(&self.root.values)
+    }
+}
+pub fn build_time_tree(node: &MasterTree, range: Range<u64>, attribution: Attribution) -> Tree {
+    let Some(root) = build_time_node_subtree(&node.root, range, attribution) else {
+        return Tree {
+            root: Node {
+                name: "root".to_string(),
+                values: trace_zero(),
+                children: vec![],
+                color: None,
+            },
+            zoomed_from: None,
+        };
     };
-    root.calculate_offset(0, 0);
     Tree {
-        root: root,
-        time_range: range,
+        root,
+        zoomed_from: None,
     }
 }
-fn build_time_node_subtree(node: &MasterNode, time_range: Range<u64>) -> Option<Node> {
-    let overlaps = node.time_overlaps(1, time_range.clone());
-
-    if overlaps.is_none() {
-        return None;
-    }
-    let unwrapped_over = overlaps.unwrap();
+fn build_time_node_subtree(
+    node: &MasterNode,
+    time_range: Range<u64>,
+    attribution: Attribution,
+) -> Option<Node> {
+    let unwrapped_over = node.time_overlaps(1, time_range.clone(), attribution)?;
     let mut new_node = Node {
         name: node.name.clone(),
         values: unwrapped_over[0].clone(),
         children: vec![],
-        offsets: trace_zero(),
         color: node.color,
     };
     for child in &node.children {
-        let n = build_time_node_subtree(child, time_range.clone());
-        if n.is_some() {
-            new_node.children.push(n.unwrap());
+        if let Some(n) = build_time_node_subtree(child, time_range.clone(), attribution) {
+            new_node.children.push(n);
         }
     }
     Some(new_node)
 }
 pub fn build_thread_tree(node: &MasterTree, thread: usize) -> Tree {
-    let Some(mut root )= build_thread_subtree(&node.root, thread) else {
-        return Tree{ root: Node{ name: "root".to_string(), values: trace_zero(), children: vec![], color: None, offsets: trace_zero() }, time_range: 0..0 };
+    build_matching_tree(node, &|t| t == thread)
+}
+///the flamegraph of every thread in one process, `thread_processes` is from `ThreadRegistry`
+pub fn build_process_tree(node: &MasterTree, process: usize, thread_processes: &[usize]) -> Tree {
+    build_matching_tree(node, &|t| thread_processes.get(t) == Some(&process))
+}
+///the flamegraph of the threads `keep` accepts
+fn build_matching_tree(node: &MasterTree, keep: &dyn Fn(usize) -> bool) -> Tree {
+    let Some(root) = build_matching_subtree(&node.root, keep) else {
+        return Tree {
+            root: Node {
+                name: "root".to_string(),
+                values: trace_zero(),
+                children: vec![],
+                color: None,
+            },
+            zoomed_from: None,
+        };
     };
-    root.calculate_offset(0, 0);
     Tree {
-        root: root,
-        time_range: 0..0,
+        root,
+        zoomed_from: None,
     }
 }
-fn build_thread_subtree(node: &MasterNode, thread: usize) -> Option<Node> {
-    let overlaps = node.single_tread_overlap(thread);
-
-    if overlaps.is_none() {
-        return None;
-    }
-    let unwrapped_over = overlaps.unwrap();
+fn build_matching_subtree(node: &MasterNode, keep: &dyn Fn(usize) -> bool) -> Option<Node> {
+    let unwrapped_over = node.matching_overlap(keep)?;
     let mut new_node = Node {
         name: node.name.clone(),
         values: unwrapped_over[0].clone(),
         children: vec![],
-        offsets: trace_zero(),
         color: node.color,
     };
     for child in &node.children {
-        let n = build_thread_subtree(child, thread);
-        if n.is_some() {
-            new_node.children.push(n.unwrap());
+        if let Some(n) = build_matching_subtree(child, keep) {
+            new_node.children.push(n);
         }
     }
     Some(new_node)
 }
-impl Node {
-    ///once the tree is already constructed, loop through the whole thing and offset children
-    fn calculate_offset(
-        &mut self,
-        starting_dur_offset: u64,
-        starting_val_offset: u64,
-    ) -> &mut Node {
-        let mut dur_offset = starting_dur_offset;
-        let mut val_offset = starting_val_offset;
-        for node in &mut self.children {
-            node.offsets.dur = dur_offset;
-            node.offsets.value = val_offset;
-            node.calculate_offset(dur_offset, val_offset);
-            dur_offset += node.values.dur;
-            val_offset += node.values.value;
-        }
-        self
-    }
-}
 impl MasterNode {
-    //maybe theres a fast overlap that doesnt require looking at each cell
-    pub fn time_overlaps(&self, num_graphs: usize, range: Range<u64>) -> Option<Vec<TraceValues>> {
+    ///every value of this node added together
+    pub fn total(&self) -> TraceValues {
+        let mut total = trace_zero();
+        for value in &self.values {
+            total.add(value);
+        }
+        total
+    }
+    ///sorts the values by start and builds the time index for this node and all below it
+    pub fn build_index(&mut self) {
+        self.values.sort_by_key(|v| v.start);
+        let mut index = TimeIndex {
+            start_sums: Vec::with_capacity(self.values.len() + 1),
+            end_sums: Vec::with_capacity(self.values.len() + 1),
+            ..Default::default()
+        };
+        let num_metrics = self
+            .values
+            .iter()
+            .map(|v| v.metrics.len())
+            .max()
+            .unwrap_or(0);
+        index.by_start = Totals::new(self.values.iter(), num_metrics);
+        let mut by_last: Vec<&TraceValues> = self.values.iter().collect();
+        by_last.sort_by_key(|v| last_instant(v));
+        index.lasts = by_last.iter().map(|v| last_instant(v)).collect();
+        let mut sum = 0;
+        index.end_sums.push(sum);
+        for value in &by_last {
+            sum += (value.start + value.dur) as u128;
+            index.end_sums.push(sum);
+        }
+        index.by_last = Totals::new(by_last.into_iter(), num_metrics);
+        let leaves = self.values.len().next_power_of_two();
+        index.max_ends = vec![0; 2 * leaves];
+        for (i, value) in self.values.iter().enumerate() {
+            index.max_ends[leaves + i] = value.start + value.dur;
+        }
+        for i in (1..leaves).rev() {
+            index.max_ends[i] = index.max_ends[2 * i].max(index.max_ends[2 * i + 1]);
+        }
+        let mut sum = 0;
+        index.start_sums.push(sum);
+        for value in &self.values {
+            sum += value.start as u128;
+            index.start_sums.push(sum);
+        }
+        self.index = index;
+        for child in &mut self.children {
+            child.build_index();
+        }
+    }
+    ///total time this node's events spend before `time`
+    fn time_before(&self, time: u64) -> u128 {
+        let index = &self.index;
+        let started = self.values.partition_point(|v| v.start < time);
+        let ended = index.lasts.partition_point(|last| *last < time);
+        //every started event counts the time since its start, minus what came after its end
+        //an event ending at `time` counts its whole duration either way
+        (started as u128 * time as u128 - index.start_sums[started])
+            - (ended as u128 * time as u128 - index.end_sums[ended])
+    }
+    ///calls `found` with every event that starts before `time` and ends after it
+    fn crossing(&self, time: u64, found: &mut impl FnMut(&TraceValues)) {
+        let started = self.values.partition_point(|v| v.start < time);
+        let leaves = self.index.max_ends.len() / 2;
+        //branches are skipped once they only hold events past `started` or ended by `time`
+        let mut branches = vec![(1, 0..leaves)];
+        while let Some((branch, events)) = branches.pop() {
+            if events.start >= started || self.index.max_ends[branch] <= time {
+                continue;
+            }
+            if events.len() == 1 {
+                found(&self.values[events.start]);
+                continue;
+            }
+            let middle = (events.start + events.end) / 2;
+            branches.push((2 * branch, events.start..middle));
+            branches.push((2 * branch + 1, middle..events.end));
+        }
+    }
+    ///value and metrics of everything before `time`, with the events crossing `time` split
+    ///by how much of their duration came before it
+    fn amounts_before(&self, time: u64) -> Vec<f64> {
+        let ended = self.index.lasts.partition_point(|last| *last < time);
+        let mut amounts = self.index.by_last.between(0..ended);
+        self.crossing(time, &mut |value| {
+            let fraction = (time - value.start) as f64 / value.dur as f64;
+            amounts[0] += value.value as f64 * fraction;
+            for (i, amount) in amounts[1..].iter_mut().enumerate() {
+                *amount += value.metric(i) as f64 * fraction;
+            }
+        });
+        amounts
+    }
+    ///value and metrics given to a slice, split between slices as `attribution` says
+    fn amounts_in(&self, time: Range<u64>, attribution: Attribution) -> Vec<f64> {
+        match attribution {
+            Attribution::Proportional => {
+                let before = self.amounts_before(time.start);
+                let mut amounts = self.amounts_before(time.end);
+                for (amount, before) in amounts.iter_mut().zip(before) {
+                    *amount -= before;
+                }
+                amounts
+            }
+            Attribution::Start => self.index.by_start.between(
+                self.values.partition_point(|v| v.start < time.start)
+                    ..self.values.partition_point(|v| v.start < time.end),
+            ),
+            Attribution::End => self.index.by_last.between(
+                self.index.lasts.partition_point(|last| *last < time.start)
+                    ..self.index.lasts.partition_point(|last| *last < time.end),
+            ),
+        }
+    }
+    ///this node's duration, value and metrics in each of `num_graphs` equal slices of `range`
+    pub fn time_overlaps(
+        &self,
+        num_graphs: usize,
+        range: Range<u64>,
+        attribution: Attribution,
+    ) -> Option<Vec<TraceValues>> {
         //did we have overlaps
         let mut some = false;
         if num_graphs < 1 {
             return None;
         }
-        //how big is the time slice we are considering
-        let graph_size = (range.end - range.start) / num_graphs as u64;
         let mut overlaps = vec![];
         for i in 0..num_graphs {
-            let time =
-                (i as u64 * graph_size + range.start)..((i as u64 + 1) * graph_size + range.start);
-            let mut dur_total = 0;
-            let mut val_total = 0;
-            //for each value find how much of the value fits into our considered range
-            for value in &self.values {
-                let dur = std::cmp::min(time.end, value.start + value.dur)
-                    .saturating_sub(std::cmp::max(time.start, value.start));
-                if value.dur != 0 {
-                    val_total += (dur / value.dur) * value.value;
-                    dur_total += dur;
-                }
-            }
-            overlaps.push(TraceValues {
-                start: 0,
-                dur: dur_total,
-                value: val_total,
-                thread: 0,
-            });
-            if dur_total != 0 {
+            let time = time_slice(&range, num_graphs, i);
+            let mut total = trace_zero();
+            total.dur = (self.time_before(time.end) - self.time_before(time.start)) as u64;
+            let amounts = self.amounts_in(time, attribution);
+            //shares of split events are rounded once per slice, after they are summed
+            total.value = amounts[0].max(0.0).round() as u64;
+            total.metrics = amounts[1..]
+                .iter()
+                .map(|amount| amount.max(0.0).round() as u64)
+                .collect();
+            if total.dur != 0 || total.value != 0 || total.metrics.iter().any(|m| *m != 0) {
                 some = true;
             }
+            overlaps.push(total);
         }
         if some {
             return Some(overlaps);
@@ -183,56 +401,64 @@ impl MasterNode {
     pub fn tread_overlaps(&self, num_threads: usize) -> Option<Vec<TraceValues>> {
         let mut overlaps = vec![];
         for i in 0..num_threads {
-            let mut start = 0;
-            let mut dur_total = 0;
-            let mut val_total = 0;
+            let mut total = trace_zero();
             for value in &self.values {
                 if value.thread == i {
-                    val_total += value.value;
-                    dur_total += value.dur;
-                    start = std::cmp::max(start, value.start);
+                    total.add(value);
                 }
             }
-            overlaps.push(TraceValues {
-                start: 0,
-                dur: dur_total,
-                value: val_total,
-                thread: 0,
-            });
+            overlaps.push(total);
         }
-        return Some(overlaps);
+        Some(overlaps)
     }
-    pub fn single_tread_overlap(&self, thread_to_match: usize) -> Option<Vec<TraceValues>> {
-        let mut overlaps = vec![];
-        let mut start = 0;
-        let mut dur_total = 0;
-        let mut val_total = 0;
+    ///one total per process, `thread_processes` gives the process of each thread
+    pub fn process_overlaps(
+        &self,
+        thread_processes: &[usize],
+        num_processes: usize,
+    ) -> Option<Vec<TraceValues>> {
+        let mut overlaps = vec![trace_zero(); num_processes];
         for value in &self.values {
-            if value.thread == thread_to_match {
-                val_total += value.value;
-                dur_total += value.dur;
-                start = std::cmp::max(start, value.start);
+            if let Some(total) = thread_processes
+                .get(value.thread)
+                .and_then(|p| overlaps.get_mut(*p))
+            {
+                total.add(value);
             }
         }
-        overlaps.push(TraceValues {
-            start: 0,
-            dur: dur_total,
-            value: val_total,
-            thread: 0,
-        });
-
-        return Some(overlaps);
+        Some(overlaps)
+    }
+    pub fn matching_overlap(&self, keep: &dyn Fn(usize) -> bool) -> Option<Vec<TraceValues>> {
+        let mut total = trace_zero();
+        for value in &self.values {
+            if keep(value.thread) {
+                total.add(value);
+            }
+        }
+        Some(vec![total])
     }
 }
 impl MasterTree {
-    fn add_trace(&mut self, trace: &Trace) -> &mut MasterNode {
-        let mut iter = &mut self.root;
-        iter.values.push(TraceValues {
+    ///the node at the end of the call path `path`, the root for an empty path
+    ///None when the profile does not have that call path
+    pub fn zoomed(&self, path: &[String]) -> Option<&MasterNode> {
+        let mut node = &self.root;
+        for name in path {
+            node = node.children.iter().find(|c| c.name == *name)?;
+        }
+        Some(node)
+    }
+    ///`thread` is the trace's index in the thread registry
+    fn add_trace(&mut self, trace: &Trace, thread: usize, metrics: Vec<u64>) -> &mut MasterNode {
+        let values = TraceValues {
             start: trace.start,
             dur: trace.dur,
             value: trace.value,
-            thread: trace.tid,
-        });
+            thread,
+            metrics,
+        };
+        let mut iter = &mut self.root;
+        iter.values.push(values.clone());
         for stackframe in trace.stack.iter().rev() {
             iter = find_str(
                 iter,
@@ -240,20 +466,10 @@ impl MasterTree {
                 self.color_scheme,
                 self.color_salt,
             );
-            iter.values.push(TraceValues {
-                start: trace.start,
-                dur: trace.dur,
-                value: trace.value,
-                thread: trace.tid,
-            });
+            iter.values.push(values.clone());
         }
         iter = find_str(iter, trace.name.clone(), self.color_scheme, self.color_salt);
-        iter.values.push(TraceValues {
-            start: trace.start,
-            dur: trace.dur,
-            value: trace.value,
-            thread: trace.tid,
-        });
+        iter.values.push(values);
         fn find_str(
             iter: &mut MasterNode,
             str: String,
@@ -284,7 +500,7 @@ impl MasterTree {
     }
 }
 fn modify_color(node: &mut MasterNode, trace: &String, color: [f32; 4]) {
-    if node.name == trace.to_owned() {
+    if node.name == *trace {
         node.color = Some(color);
     }
     for child in &mut node.children {
@@ -298,12 +514,28 @@ fn new_color_scheme_nodes(node: &mut MasterNode, scheme: ColorScheme, salt: u32)
     }
 }
 
+///slice `i` of `range` split into `num_graphs` equal slices, whole numbers so that the graphs
+///and the inspector agree on every boundary, a remainder past the last slice is left out
+pub fn time_slice(range: &Range<u64>, num_graphs: usize, i: usize) -> Range<u64> {
+    let size = (range.end - range.start) / num_graphs.max(1) as u64;
+    (range.start + i as u64 * size)..(range.start + (i as u64 + 1) * size)
+}
+///the last instant an event covers, events without a duration only cover their start
+fn last_instant(value: &TraceValues) -> u64 {
+    if value.dur == 0 {
+        value.start
+    } else {
+        value.start + value.dur - 1
+    }
+}
 fn master_root() -> MasterNode {
     MasterNode {
         name: "root".to_string(),
         values: vec![],
         children: vec![],
         color: None,
+        index: TimeIndex::default(),
+        baseline: None,
     }
 }
 
@@ -321,7 +553,7 @@ fn generate_hash(name: &str, color_salt: u32) -> f64 {
             }
             hash += weight * ((c as u32 + color_salt) % MODULOUS) as f64;
             max_hash += weight * (MODULOUS - 1) as f64;
-            weight = weight * 0.7;
+            weight *= 0.7;
         }
         if max_hash > 0. {
             hash / max_hash
@@ -348,15 +580,17 @@ pub fn generate_color_vector(name: &str, color_mod: u32) -> f64 {
     vector
 }
 
-pub fn color_from_scheme(name: &String, scheme: ColorScheme, color_mod: u32) -> [f32; 4] {
+pub fn color_from_scheme(name: &str, scheme: ColorScheme, color_mod: u32) -> [f32; 4] {
     match scheme {
         ColorScheme::Flame => color_scheme_flame(name, color_mod),
         ColorScheme::Ice => color_scheme_ice(name, color_mod),
         ColorScheme::Greyscale => color_scheme_greyscale(name, color_mod),
         ColorScheme::Rainbow => color_scheme_rainbow(name, color_mod),
+        //the trend needs the whole tree, see `trend_colors`, until then nodes are neutral
+        ColorScheme::Trend => diverging_color(0.0),
     }
 }
-pub fn color_scheme_rainbow(name: &String, color_mod: u32) -> [f32; 4] {
+pub fn color_scheme_rainbow(name: &str, color_mod: u32) -> [f32; 4] {
     let mut s = DefaultHasher::new();
     name.hash(&mut s);
     color_mod.hash(&mut s);
@@ -369,27 +603,27 @@ pub fn color_scheme_rainbow(name: &String, color_mod: u32) -> [f32; 4] {
         1.0,
     ]
 }
-pub fn color_scheme_greyscale(name: &String, color_mod: u32) -> [f32; 4] {
+pub fn color_scheme_greyscale(name: &str, color_mod: u32) -> [f32; 4] {
     let vector = generate_color_vector(name, color_mod);
 
-    let r = (0 + (255.0 * (1.0 - vector)).round() as u8) as f32;
-    let g = (0 + (255.0 * (1.0 - vector)).round() as u8) as f32;
-    let b = (0 + (255.0 * (1.0 - vector)).round() as u8) as f32;
+    let r = ((255.0 * (1.0 - vector)).round() as u8) as f32;
+    let g = ((255.0 * (1.0 - vector)).round() as u8) as f32;
+    let b = ((255.0 * (1.0 - vector)).round() as u8) as f32;
     [r / 255.0, g / 255.0, b / 255.0, 1.0]
 }
-pub fn color_scheme_flame(name: &String, color_mod: u32) -> [f32; 4] {
+pub fn color_scheme_flame(name: &str, color_mod: u32) -> [f32; 4] {
     let vector = generate_color_vector(name, color_mod);
 
     let r = (200 + (55.0 * vector).round() as u8) as f32;
-    let g = (0 + (230.0 * (1.0 - vector)).round() as u8) as f32;
-    let b = (0 + (55.0 * (1.0 - vector)).round() as u8) as f32;
+    let g = ((230.0 * (1.0 - vector)).round() as u8) as f32;
+    let b = ((55.0 * (1.0 - vector)).round() as u8) as f32;
     [r / 255.0, g / 255.0, b / 255.0, 1.0]
 }
-pub fn color_scheme_ice(name: &String, color_mod: u32) -> [f32; 4] {
+pub fn color_scheme_ice(name: &str, color_mod: u32) -> [f32; 4] {
     let vector = generate_color_vector(name, color_mod);
 
-    let r = (0 + (55.0 * (1.0 - vector)).round() as u8) as f32;
-    let g = (0 + (230.0 * (1.0 - vector)).round() as u8) as f32;
+    let r = ((55.0 * (1.0 - vector)).round() as u8) as f32;
+    let g = ((230.0 * (1.0 - vector)).round() as u8) as f32;
     let b = (200 + (55.0 * vector).round() as u8) as f32;
     [r / 255.0, g / 255.0, b / 255.0, 1.0]
 }
@@ -402,23 +636,44 @@ pub struct Trace {
     pub start: u64,
     pub dur: u64,
     pub value: u64,
+    ///process id, 0 when the format does not have processes
+    #[serde(default)]
+    pub pid: usize,
     pub tid: usize,
+    ///extra measurements such as cycles or cache misses, keyed by name
+    #[serde(default)]
+    pub metrics: BTreeMap<String, u64>,
+    ///names for the trace's thread and process, only needed on one trace per thread
+    #[serde(default, skip_serializing_if = "ThreadDetails::is_empty")]
+    pub thread: ThreadDetails,
+}
+///what a file says about a thread besides its ids
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ThreadDetails {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub process_name: Option<String>,
+    ///anything else worth showing, such as an MPI rank or GPU stream
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+impl ThreadDetails {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.process_name.is_none() && self.metadata.is_empty()
+    }
 }
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub struct Stackframe {
     pub name: String,
 }
-pub fn grow_master_tree() -> MasterTree {
-    let data = load_data();
-    let mut tree = MasterTree {
-        root: master_root(),
-        color_scheme: DEFAULT_COLOR,
-        color_salt: 1,
-    };
+///builds the master tree from traces that are already in memory
+pub fn grow_master_tree(data: &[Trace]) -> (MasterTree, TreeInfo) {
+    let mut builder = TreeBuilder::new();
     for trace in data {
-        tree.add_trace(&trace);
+        builder.add(trace);
     }
-    tree
+    builder.finish()
 }
 
 #[derive(Debug, Clone)]
@@ -428,25 +683,688 @@ pub struct TreeInfo {
     pub start: u64,
     pub end: u64,
     pub nodes: u64,
+    ///names of the metrics carried by the traces, in the order they were first seen
+    pub metrics: Vec<String>,
+    pub threads: ThreadRegistry,
+    ///the sample types a user can choose between, empty for formats with a single value
+    pub sample_types: Vec<String>,
+}
+///a thread seen in the traces
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreadEntry {
+    pub pid: usize,
+    pub tid: usize,
+    ///index of the thread's process in `ThreadRegistry.processes`
+    pub process: usize,
+    pub name: String,
+    pub metadata: BTreeMap<String, String>,
+}
+///a process seen in the traces
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessEntry {
+    pub pid: usize,
+    pub name: String,
+}
+///gives every (pid, tid) pair a dense index in the order it is first seen,
+///so large or sparse ids stay small and threads of different processes never collide
+#[derive(Debug, Clone, Default)]
+pub struct ThreadRegistry {
+    pub entries: Vec<ThreadEntry>,
+    ///processes in the order they were first seen
+    pub processes: Vec<ProcessEntry>,
+    index: HashMap<(usize, usize), usize>,
+    process_index: HashMap<usize, usize>,
+}
+impl ThreadRegistry {
+    ///the index of a thread, which is added the first time it is seen
+    pub fn register(&mut self, pid: usize, tid: usize) -> usize {
+        if let Some(index) = self.index.get(&(pid, tid)) {
+            return *index;
+        }
+        let next = self.processes.len();
+        let process = *self.process_index.entry(pid).or_insert(next);
+        if process == next {
+            self.processes.push(ProcessEntry {
+                pid,
+                name: format!("Process {}", pid),
+            });
+        }
+        let name = if pid == 0 {
+            format!("Thread {}", tid)
+        } else {
+            format!("Process {} thread {}", pid, tid)
+        };
+        self.entries.push(ThreadEntry {
+            pid,
+            tid,
+            process,
+            name,
+            metadata: BTreeMap::new(),
+        });
+        self.index.insert((pid, tid), self.entries.len() - 1);
+        self.entries.len() - 1
+    }
+    ///names a thread and its process, metadata is added to what the thread already has
+    pub fn describe(&mut self, thread: usize, details: &ThreadDetails) {
+        let Some(entry) = self.entries.get_mut(thread) else {
+            return;
+        };
+        if let Some(name) = &details.name {
+            entry.name = name.clone();
+        }
+        entry
+            .metadata
+            .extend(details.metadata.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if let Some(name) = &details.process_name {
+            self.processes[entry.process].name = name.clone();
+        }
+    }
+    ///the name of a thread by index, threads the registry has not seen are numbered
+    pub fn thread_name(&self, thread: usize) -> String {
+        self.entries
+            .get(thread)
+            .map_or_else(|| format!("Thread {}", thread), |t| t.name.clone())
+    }
+    pub fn process_name(&self, process: usize) -> String {
+        self.processes
+            .get(process)
+            .map_or_else(|| format!("Process {}", process), |p| p.name.clone())
+    }
+    ///the process index of every thread, in thread order
+    pub fn thread_processes(&self) -> Vec<usize> {
+        self.entries.iter().map(|t| t.process).collect()
+    }
+    ///at least one, so an empty profile still has a slice to draw
+    pub fn num_processes(&self) -> usize {
+        self.processes.len().max(1)
+    }
+}
+///grows the master tree and gathers its info one trace at a time,
+///so traces never need to be held in memory all at once
+pub struct TreeBuilder {
+    tree: MasterTree,
+    info: TreeInfo,
+    metric_index: HashMap<String, usize>,
 }
-///gets relevent info from a list of traces
-pub fn get_info<'a>(
-    traces: impl Iterator<Item = &'a Trace> + Clone + ExactSizeIterator,
-) -> TreeInfo {
-    TreeInfo {
-        max_depth: traces.clone().map(|i| i.stack.len()).max().unwrap_or(0),
-        num_threads: traces.clone().map(|i| i.tid).max().unwrap_or(0) + 1,
-        start: traces.clone().map(|i| i.start).min().unwrap_or(0),
-        end: traces.clone().map(|i| i.start + i.dur).max().unwrap_or(0),
-        nodes: traces.len() as u64,
+impl TreeBuilder {
+    pub fn new() -> Self {
+        TreeBuilder {
+            tree: MasterTree {
+                root: master_root(),
+                color_scheme: DEFAULT_COLOR,
+                color_salt: 1,
+            },
+            info: TreeInfo {
+                max_depth: 0,
+                num_threads: 1,
+                start: u64::MAX,
+                end: 0,
+                nodes: 0,
+                metrics: vec![],
+                threads: Default::default(),
+                sample_types: vec![],
+            },
+            metric_index: HashMap::new(),
+        }
+    }
+    pub fn add(&mut self, trace: &Trace) {
+        let mut metrics = vec![];
+        for (name, amount) in &trace.metrics {
+            let index = match self.metric_index.get(name) {
+                Some(index) => *index,
+                None => {
+                    self.info.metrics.push(name.clone());
+                    self.metric_index
+                        .insert(name.clone(), self.info.metrics.len() - 1);
+                    self.info.metrics.len() - 1
+                }
+            };
+            if metrics.len() <= index {
+                metrics.resize(index + 1, 0);
+            }
+            metrics[index] = *amount;
+        }
+        let info = &mut self.info;
+        let thread = info.threads.register(trace.pid, trace.tid);
+        if !trace.thread.is_empty() {
+            info.threads.describe(thread, &trace.thread);
+        }
+        self.tree.add_trace(trace, thread, metrics);
+        info.max_depth = info.max_depth.max(trace.stack.len());
+        info.num_threads = info.threads.entries.len();
+        info.start = info.start.min(trace.start);
+        info.end = info.end.max(trace.start + trace.dur);
+        info.nodes += 1;
+    }
+    pub fn finish(mut self) -> (MasterTree, TreeInfo) {
+        if self.info.nodes == 0 {
+            self.info.start = 0;
+        }
+        self.tree.root.build_index();
+        (self.tree, self.info)
     }
 }
-fn load_data() -> Vec<Trace> {
-    serde_json::from_str(INPUT_TRACE).unwrap()
+///everything that can go wrong while reading a trace file
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceError {
+    Io(String),
+    Decode(String),
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Schema {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    MissingField {
+        trace: usize,
+        field: &'static str,
+    },
+    NegativeTime {
+        trace: usize,
+        field: &'static str,
+    },
+    TimeOverflow {
+        trace: usize,
+    },
+    Empty,
 }
-pub fn get_input_info() -> TreeInfo {
-    let data = load_data();
-    get_info(data.iter())
+impl std::fmt::Display for TraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceError::Io(message) => write!(f, "could not read file: {}", message),
+            TraceError::Decode(message) => write!(f, "could not decode profile: {}", message),
+            TraceError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "syntax error at line {} column {}: {}",
+                line, column, message
+            ),
+            TraceError::Schema {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "unexpected data at line {} column {}: {}",
+                line, column, message
+            ),
+            TraceError::MissingField { trace, field } => {
+                write!(f, "trace {} is missing the `{}` field", trace, field)
+            }
+            TraceError::NegativeTime { trace, field } => {
+                write!(f, "trace {} has a negative `{}`", trace, field)
+            }
+            TraceError::TimeOverflow { trace } => {
+                write!(
+                    f,
+                    "trace {} ends past the largest representable time",
+                    trace
+                )
+            }
+            TraceError::Empty => write!(f, "the file does not contain any traces"),
+        }
+    }
+}
+impl std::error::Error for TraceError {}
+impl From<std::io::Error> for TraceError {
+    fn from(error: std::io::Error) -> Self {
+        TraceError::Io(error.to_string())
+    }
+}
+impl From<serde_json::Error> for TraceError {
+    fn from(error: serde_json::Error) -> Self {
+        let line = error.line();
+        let column = error.column();
+        //serde_json appends the position to its message, it is shown separately here
+        let message = error.to_string();
+        let message = message
+            .strip_suffix(&format!(" at line {} column {}", line, column))
+            .unwrap_or(&message)
+            .to_string();
+        match error.classify() {
+            serde_json::error::Category::Io => TraceError::Io(message),
+            serde_json::error::Category::Data => TraceError::Schema {
+                line,
+                column,
+                message,
+            },
+            _ => TraceError::Syntax {
+                line,
+                column,
+                message,
+            },
+        }
+    }
+}
+///a trace as it appears in the file, before it has been checked
+#[derive(serde::Deserialize)]
+struct RawTrace {
+    id: Option<usize>,
+    name: Option<String>,
+    stack: Option<Vec<Stackframe>>,
+    start: Option<i128>,
+    dur: Option<i128>,
+    value: Option<u64>,
+    pid: Option<usize>,
+    tid: Option<usize>,
+    metrics: Option<BTreeMap<String, u64>>,
+    thread: Option<ThreadDetails>,
 }
+impl RawTrace {
+    fn validate(self, trace: usize) -> Result<Trace, TraceError> {
+        let missing = |field| TraceError::MissingField { trace, field };
+        let time = |value: Option<i128>, field| {
+            let value = value.ok_or(missing(field))?;
+            if value < 0 {
+                return Err(TraceError::NegativeTime { trace, field });
+            }
+            u64::try_from(value).map_err(|_| TraceError::TimeOverflow { trace })
+        };
+        let start = time(self.start, "start")?;
+        let dur = time(self.dur, "dur")?;
+        if start.checked_add(dur).is_none() {
+            return Err(TraceError::TimeOverflow { trace });
+        }
+        Ok(Trace {
+            id: self.id.ok_or(missing("id"))?,
+            name: self.name.ok_or(missing("name"))?,
+            stack: self.stack.ok_or(missing("stack"))?,
+            start,
+            dur,
+            value: self.value.ok_or(missing("value"))?,
+            pid: self.pid.unwrap_or(0),
+            tid: self.tid.ok_or(missing("tid"))?,
+            metrics: self.metrics.unwrap_or_default(),
+            thread: self.thread.unwrap_or_default(),
+        })
+    }
+}
+///visits the trace array element by element, adding each trace as soon as it is read
+struct TraceVisitor<'a> {
+    builder: &'a mut TreeBuilder,
+    error: &'a mut Option<TraceError>,
+}
+impl<'de, 'a> serde::de::Visitor<'de> for TraceVisitor<'a> {
+    type Value = ();
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a list of traces")
+    }
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut i = 0;
+        while let Some(raw) = seq.next_element::<RawTrace>()? {
+            match raw.validate(i) {
+                Ok(trace) => self.builder.add(&trace),
+                Err(e) => {
+                    //serde can only carry a message, the typed error is handed back separately
+                    *self.error = Some(e);
+                    return Err(serde::de::Error::custom("invalid trace"));
+                }
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+}
+///parses the raw bytes of a trace file straight into a master tree and its info
+pub fn load_data(input: &[u8]) -> Result<(MasterTree, TreeInfo), TraceError> {
+    load_data_from(serde_json::Deserializer::from_slice(input))
+}
+///parses a trace file as it is read, so the file itself is never held in memory
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_data_from_reader(
+    input: impl std::io::Read,
+) -> Result<(MasterTree, TreeInfo), TraceError> {
+    load_data_from(serde_json::Deserializer::from_reader(input))
+}
+fn load_data_from<'de, R: serde_json::de::Read<'de>>(
+    mut deserializer: serde_json::Deserializer<R>,
+) -> Result<(MasterTree, TreeInfo), TraceError> {
+    let mut builder = TreeBuilder::new();
+    let mut error = None;
+    let result = serde::Deserializer::deserialize_seq(
+        &mut deserializer,
+        TraceVisitor {
+            builder: &mut builder,
+            error: &mut error,
+        },
+    )
+    .and_then(|_| deserializer.end());
+    if let Some(e) = error {
+        return Err(e);
+    }
+    result?;
+    if builder.info.nodes == 0 {
+        return Err(TraceError::Empty);
+    }
+    Ok(builder.finish())
+}
+///a trace for tests, one call of `path` written outermost first like `main;a;x`, lasting
+///10 from 0 with a value of 1, other fields are set with `..test_trace(path)`
+#[cfg(test)]
+pub fn test_trace(path: &str) -> Trace {
+    let mut frames: Vec<Stackframe> = path
+        .split(';')
+        .rev()
+        .map(|name| Stackframe {
+            name: name.to_string(),
+        })
+        .collect();
+    let name = frames.remove(0).name;
+    Trace {
+        id: 0,
+        name,
+        stack: frames,
+        start: 0,
+        dur: 10,
+        value: 1,
+        pid: 0,
+        tid: 0,
+        metrics: Default::default(),
+        thread: Default::default(),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///a tree holding one function called once per (start, dur, value)
+    fn tree_of(events: &[(u64, u64, u64)]) -> MasterTree {
+        grow_master_tree(&traces_of(events)).0
+    }
+    fn traces_of(events: &[(u64, u64, u64)]) -> Vec<Trace> {
+        events
+            .iter()
+            .map(|(start, dur, value)| Trace {
+                start: *start,
+                dur: *dur,
+                value: *value,
+                metrics: BTreeMap::from([("cycles".to_string(), value * 2)]),
+                ..test_trace("f")
+            })
+            .collect()
+    }
+    ///value of each of two 20ns slices over 0..40
+    fn slice_values(tree: &MasterTree, attribution: Attribution) -> Vec<u64> {
+        tree.root
+            .time_overlaps(2, 0..40, attribution)
+            .unwrap()
+            .iter()
+            .map(|v| v.value)
+            .collect()
+    }
+    const ALL: [Attribution; 3] = [
+        Attribution::Proportional,
+        Attribution::Start,
+        Attribution::End,
+    ];
 
-const INPUT_TRACE: &str = include_str!("./../../../lulesh.json");
+    #[test]
+    fn event_across_boundary_is_split_by_policy() {
+        let tree = tree_of(&[(10, 20, 100)]);
+        assert_eq!(slice_values(&tree, Attribution::Proportional), vec![50, 50]);
+        assert_eq!(slice_values(&tree, Attribution::Start), vec![100, 0]);
+        assert_eq!(slice_values(&tree, Attribution::End), vec![0, 100]);
+    }
+    #[test]
+    fn duration_is_split_by_time_whatever_the_policy() {
+        let tree = tree_of(&[(10, 20, 100)]);
+        for attribution in ALL {
+            let slices = tree.root.time_overlaps(2, 0..40, attribution).unwrap();
+            assert_eq!(slices[0].dur, 10);
+            assert_eq!(slices[1].dur, 10);
+        }
+    }
+    #[test]
+    fn event_ending_on_boundary_stays_in_its_slice() {
+        let tree = tree_of(&[(0, 20, 10)]);
+        for attribution in ALL {
+            assert_eq!(slice_values(&tree, attribution), vec![10, 0]);
+        }
+    }
+    #[test]
+    fn event_starting_on_boundary_belongs_to_next_slice() {
+        let tree = tree_of(&[(20, 10, 10)]);
+        for attribution in ALL {
+            assert_eq!(slice_values(&tree, attribution), vec![0, 10]);
+        }
+    }
+    #[test]
+    fn instant_event_on_boundary_belongs_to_next_slice() {
+        let tree = tree_of(&[(20, 0, 7)]);
+        for attribution in ALL {
+            assert_eq!(slice_values(&tree, attribution), vec![0, 7]);
+        }
+    }
+    #[test]
+    fn partial_overlap_with_range_edges() {
+        let tree = tree_of(&[(10, 20, 100)]);
+        let slice = tree
+            .root
+            .time_overlaps(1, 15..35, Attribution::Proportional)
+            .unwrap();
+        assert_eq!(slice[0].dur, 15);
+        assert_eq!(slice[0].value, 75);
+    }
+    #[test]
+    fn metrics_are_split_like_value() {
+        let tree = tree_of(&[(10, 20, 100), (25, 10, 10)]);
+        let slices = tree
+            .root
+            .time_overlaps(2, 0..40, Attribution::Proportional)
+            .unwrap();
+        assert_eq!(slices[0].metrics, vec![100]);
+        assert_eq!(slices[1].metrics, vec![120]);
+    }
+    #[test]
+    fn events_outside_the_range_are_ignored() {
+        let tree = tree_of(&[(50, 10, 10)]);
+        for attribution in ALL {
+            assert!(tree.root.time_overlaps(2, 0..40, attribution).is_none());
+        }
+    }
+    ///dur, value and the first metric of each slice, worked out event by event
+    fn brute_overlaps(
+        events: &[(u64, u64, u64)],
+        num_graphs: u64,
+        range: Range<u64>,
+        attribution: Attribution,
+    ) -> Vec<(u64, f64, f64)> {
+        //the share of an event's value that came before `time`
+        let before = |(start, dur, _): (u64, u64, u64), time: u64| {
+            if dur == 0 {
+                (start < time) as u64 as f64
+            } else {
+                (time.clamp(start, start + dur) - start) as f64 / dur as f64
+            }
+        };
+        let size = (range.end - range.start) / num_graphs;
+        (0..num_graphs)
+            .map(|i| {
+                let time = (range.start + i * size)..(range.start + (i + 1) * size);
+                let mut slice = (0, 0.0, 0.0);
+                for event in events {
+                    let (start, dur, value) = *event;
+                    let end = start + dur;
+                    slice.0 += end.min(time.end).saturating_sub(start.max(time.start));
+                    let share = match attribution {
+                        Attribution::Proportional => {
+                            before(*event, time.end) - before(*event, time.start)
+                        }
+                        Attribution::Start => time.contains(&start) as u64 as f64,
+                        Attribution::End => time.contains(&last_instant(&TraceValues {
+                            start,
+                            dur,
+                            ..trace_zero()
+                        })) as u64 as f64,
+                    };
+                    slice.1 += value as f64 * share;
+                    slice.2 += (value * 2) as f64 * share;
+                }
+                slice
+            })
+            .collect()
+    }
+    #[test]
+    fn time_index_matches_brute_force() {
+        //xorshift, so every run checks the same cases
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = move |below: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % below
+        };
+        for _ in 0..200 {
+            let events: Vec<(u64, u64, u64)> = (0..1 + random(40))
+                .map(|_| {
+                    //a quarter of the events are instants, with no duration
+                    let dur = if random(4) == 0 { 0 } else { random(300) };
+                    (random(1000), dur, random(1000))
+                })
+                .collect();
+            let tree = tree_of(&events);
+            let start = random(1200);
+            let range = start..start + 1 + random(1200);
+            let num_graphs = 1 + random(8);
+            for attribution in ALL {
+                let expected = brute_overlaps(&events, num_graphs, range.clone(), attribution);
+                let slices =
+                    tree.root
+                        .time_overlaps(num_graphs as usize, range.clone(), attribution);
+                let Some(slices) = slices else {
+                    assert!(expected.iter().all(|e| e.0 == 0 && e.1.round() == 0.0));
+                    continue;
+                };
+                for (slice, (dur, value, metric)) in slices.iter().zip(expected) {
+                    assert_eq!(slice.dur, dur, "{:?} {:?} {:?}", events, range, attribution);
+                    //the index sums before rounding too, but in another order
+                    assert!((slice.value as f64 - value.round()).abs() <= 1.0);
+                    assert!((slice.metrics[0] as f64 - metric.round()).abs() <= 1.0);
+                }
+            }
+        }
+    }
+    #[test]
+    fn zoomed_trees_keep_the_slice_total() {
+        let traces = [
+            Trace {
+                value: 30,
+                ..test_trace("main;parse")
+            },
+            Trace {
+                start: 10,
+                value: 70,
+                ..test_trace("main;draw")
+            },
+        ];
+        let (master, info) = grow_master_tree(&traces);
+        let tree = build_time_tree(&master, 0..20, Attribution::Proportional);
+        assert_eq!(tree.slice_total().value, 100);
+        let zoomed = tree
+            .clone()
+            .zoomed(&["main".to_string(), "parse".to_string()]);
+        //the root spans the node zoomed into, percentages are still of the whole slice
+        assert_eq!(zoomed.root.values.value, 30);
+        assert_eq!(zoomed.slice_total().value, 100);
+        let details = crate::app::ui_helper::NodeDetails::new(
+            &zoomed.root.children[0],
+            &zoomed,
+            &info,
+            vec![],
+        );
+        let (name, amount, percent) = &details.measures[1];
+        assert_eq!((name.as_str(), *amount), ("Value", 30));
+        assert!((percent - 30.0).abs() < 0.01);
+        let missing = tree.zoomed(&["nowhere".to_string()]);
+        assert!(missing.root.children.is_empty());
+        assert_eq!(missing.slice_total().value, 100);
+    }
+    #[test]
+    fn streamed_and_in_memory_loads_agree() {
+        let input = serde_json::to_vec(&traces_of(&[(10, 20, 100), (25, 10, 10)])).unwrap();
+        let (slice, slice_info) = load_data(&input).unwrap();
+        let (read, read_info) = load_data_from_reader(std::io::Cursor::new(&input)).unwrap();
+        let total = |tree: &MasterTree| tree.root.values.iter().map(|v| v.value).sum::<u64>();
+        assert_eq!(total(&slice), 110);
+        assert_eq!(total(&read), 110);
+        assert_eq!(slice_info.nodes, read_info.nodes);
+        assert_eq!((read_info.start, read_info.end), (10, 35));
+    }
+    #[test]
+    fn truncated_stream_is_an_error() {
+        let input = serde_json::to_vec(&traces_of(&[(10, 20, 100)])).unwrap();
+        let cut = &input[..input.len() - 2];
+        assert!(load_data_from_reader(std::io::Cursor::new(cut)).is_err());
+    }
+    ///a file of two traces with `change` made to the second
+    fn file_with(change: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>)) -> Vec<u8> {
+        let mut traces = serde_json::to_value(traces_of(&[(0, 10, 1), (10, 10, 1)])).unwrap();
+        change(traces[1].as_object_mut().unwrap());
+        serde_json::to_vec(&traces).unwrap()
+    }
+    #[test]
+    fn syntax_errors_give_their_position() {
+        let input = b"[\n  {\"id\": 0,, \"name\": \"main\"}\n]";
+        let Err(TraceError::Syntax { line, column, .. }) = load_data(input) else {
+            panic!("expected a syntax error");
+        };
+        //the second comma
+        assert_eq!((line, column), (2, 12));
+    }
+    #[test]
+    fn wrongly_typed_fields_give_their_position() {
+        let input = file_with(|trace| {
+            trace.insert("value".to_string(), "ten".into());
+        });
+        assert!(matches!(
+            load_data(&input),
+            Err(TraceError::Schema { line: 1, column, .. }) if column > 0
+        ));
+    }
+    #[test]
+    fn missing_fields_name_their_trace() {
+        let input = file_with(|trace| {
+            trace.remove("value");
+        });
+        assert!(matches!(
+            load_data(&input),
+            Err(TraceError::MissingField {
+                trace: 1,
+                field: "value"
+            })
+        ));
+    }
+    #[test]
+    fn negative_times_name_their_trace() {
+        for field in ["start", "dur"] {
+            let input = file_with(|trace| {
+                trace.insert(field.to_string(), (-5).into());
+            });
+            assert!(matches!(
+                load_data(&input),
+                Err(TraceError::NegativeTime { trace: 1, field: f }) if f == field
+            ));
+        }
+    }
+    #[test]
+    fn traces_ending_past_the_largest_time_name_their_trace() {
+        let input = file_with(|trace| {
+            trace.insert("start".to_string(), u64::MAX.into());
+        });
+        assert!(matches!(
+            load_data(&input),
+            Err(TraceError::TimeOverflow { trace: 1 })
+        ));
+    }
+    #[test]
+    fn files_without_traces_are_empty() {
+        assert!(matches!(load_data(b"[]"), Err(TraceError::Empty)));
+    }
+}
@@ -10,11 +10,49 @@ use crate::app::tree::*;
 fn screen_to_world(screen_size: egui::Rect, mouse_pos: egui::Pos2) -> egui::Pos2 {
     let mouse_zero = mouse_pos - screen_size.left_top().to_vec2();
     let screen_size = screen_size.right_bottom() - screen_size.left_top().to_vec2();
-    let mut mouse_mirror = mouse_pos.clone();
+    let mut mouse_mirror = mouse_pos;
     mouse_mirror.x = ((mouse_zero.x / screen_size.x) - 0.5) * 2.0;
     mouse_mirror.y = ((mouse_zero.y / screen_size.y) - 0.5) * -2.0;
     mouse_mirror
 }
+///where a rect of the inspector mesh lands on screen, the inverse of screen_to_world
+pub fn rect_to_screen(
+    screen_size: egui::Rect,
+    lower_corner: [f32; 3],
+    upper_corner: [f32; 3],
+    matrix: cgmath::Matrix4<f32>,
+) -> egui::Rect {
+    let to_screen = |vert: [f32; 3]| {
+        let world = transform_vert(vert, matrix);
+        egui::pos2(
+            screen_size.left() + (world[0] / 2.0 + 0.5) * screen_size.width(),
+            screen_size.top() + (0.5 - world[1] / 2.0) * screen_size.height(),
+        )
+    };
+    egui::Rect::from_two_pos(to_screen(lower_corner), to_screen(upper_corner))
+}
+///the longest start of `text` that fits in `width`, with an ellipsis when it was cut short
+///None when not even one letter and the ellipsis fit
+pub fn fit_text(text: &str, width: f32, char_width: impl Fn(char) -> f32) -> Option<String> {
+    let full: f32 = text.chars().map(&char_width).sum();
+    if full <= width {
+        return Some(text.to_string());
+    }
+    let mut room = width - char_width('…');
+    let mut fitted = String::new();
+    for c in text.chars() {
+        room -= char_width(c);
+        if room < 0.0 {
+            break;
+        }
+        fitted.push(c);
+    }
+    if fitted.is_empty() {
+        return None;
+    }
+    fitted.push('…');
+    Some(fitted)
+}
 ///retreive the nth node of a tree
 fn fetch_nth_node(tree: &Tree, n: usize) -> Option<(usize, &Node)> {
     let mut iter = Bft::new(&tree.root, |tree| tree.children.iter());
@@ -31,9 +69,32 @@ fn transform_vert(vert: [f32; 3], matrix: cgmath::Matrix4<f32>) -> [f32; 3] {
         };
     [t.x, t.y, t.z]
 }
-///lookup the node the mouse is hovering over
+///where a world space point lands on screen, None when it is behind the camera
+pub fn world_to_screen(
+    screen_size: egui::Rect,
+    point: [f32; 3],
+    matrix: cgmath::Matrix4<f32>,
+) -> Option<egui::Pos2> {
+    let t = matrix
+        * cgmath::Vector4 {
+            x: point[0],
+            y: point[1],
+            z: point[2],
+            w: 1.0,
+        };
+    if t.w <= 0.0 {
+        return None;
+    }
+    let center = screen_size.center();
+    Some(egui::pos2(
+        center.x + t.x / t.w * screen_size.width() / 2.0,
+        center.y - t.y / t.w * screen_size.height() / 2.0,
+    ))
+}
+///lookup the node the mouse is hovering over, along with its breadth first index
 pub fn inspector_lookup<'a>(
-    verts: &Vec<[f32; 3]>,
+    verts: &[[f32; 3]],
+    paths: &PathIndex,
     flamegraph: &'a Tree,
     screen_size: egui::Rect,
     mouse_pos: egui::Pos2,
@@ -49,12 +110,119 @@ pub fn inspector_lookup<'a>(
             && world.y > lower_corner[1]
             && world.y < upper_corner[1]
         {
-            return fetch_nth_node(flamegraph, 1 + (i / VERTS_IN_RECT));
+            let n = *paths.rects.get(i / VERTS_IN_RECT)?;
+            return fetch_nth_node(flamegraph, n).map(|(_, node)| (n, node));
         }
         i += VERTS_IN_RECT;
     }
     None
 }
+///every node of the tree in breadth first order, as counted by `PathIndex`
+pub fn nodes_breadth_first(flamegraph: &Tree) -> Vec<&Node> {
+    Bft::new(&flamegraph.root, |tree| tree.children.iter())
+        .map(|(_, node)| node)
+        .collect()
+}
+///the names on the call path down to the nth node, outermost first
+pub fn call_stack(flamegraph: &Tree, paths: &PathIndex, n: usize) -> Vec<String> {
+    let nodes = nodes_breadth_first(flamegraph);
+    paths
+        .path(n)
+        .iter()
+        .filter_map(|i| nodes.get(*i))
+        .map(|node| node.name.clone())
+        .collect()
+}
+///what the inspector knows about one node, pinned on a click so it can be copied
+#[derive(Debug, Clone, Default)]
+pub struct NodeDetails {
+    pub name: String,
+    ///the call path down to the node, outermost first, empty while only hovering
+    pub stack: Vec<String>,
+    ///duration, value then each metric, with its name and percent of the slice
+    pub measures: Vec<(String, u64, f32)>,
+    pub children: usize,
+    ///every node below this one, not only the direct children
+    pub descendants: usize,
+}
+impl NodeDetails {
+    pub fn new(node: &Node, flamegraph: &Tree, info: &TreeInfo, stack: Vec<String>) -> Self {
+        //percentages stay of the whole slice while the inspector is zoomed in
+        let root = flamegraph.slice_total();
+        let percent = |amount: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                amount as f32 / total as f32 / 0.01
+            }
+        };
+        let mut measures = vec![
+            (
+                "Duration".to_string(),
+                node.values.dur,
+                percent(node.values.dur, root.dur),
+            ),
+            (
+                "Value".to_string(),
+                node.values.value,
+                percent(node.values.value, root.value),
+            ),
+        ];
+        for (i, name) in info.metrics.iter().enumerate() {
+            let amount = node.values.metric(i);
+            measures.push((name.clone(), amount, percent(amount, root.metric(i))));
+        }
+        NodeDetails {
+            name: node.name.clone(),
+            stack,
+            measures,
+            children: node.children.len(),
+            descendants: Bft::new(node, |node| node.children.iter()).count() - 1,
+        }
+    }
+    ///the text shown when hovering over the node
+    pub fn hover_text(&self) -> String {
+        let mut text = format!(" Name:        {}", self.name);
+        for (name, amount, percent) in &self.measures {
+            text += &format!("\n {}:    {}    {}% of this slice", name, amount, percent);
+        }
+        text += &format!(
+            "\n Children:    {}    {} below",
+            self.children, self.descendants
+        );
+        text
+    }
+    ///the column names of `tsv_row`
+    pub fn tsv_header(&self) -> String {
+        let mut columns = vec!["Name".to_string(), "Stack".to_string()];
+        for (name, _, _) in &self.measures {
+            columns.push(name.clone());
+            columns.push(format!("{} %", name));
+        }
+        columns.push("Children".to_string());
+        columns.push("Below".to_string());
+        tsv(&columns)
+    }
+    ///one tab separated line, the stack is joined with `;` like folded stacks
+    pub fn tsv_row(&self) -> String {
+        let mut columns = vec![self.name.clone(), self.stack.join(";")];
+        for (_, amount, percent) in &self.measures {
+            columns.push(amount.to_string());
+            columns.push(percent.to_string());
+        }
+        columns.push(self.children.to_string());
+        columns.push(self.descendants.to_string());
+        tsv(&columns)
+    }
+}
+///joins columns with tabs, tabs and line breaks inside a column would split it so become spaces
+fn tsv(columns: &[String]) -> String {
+    columns
+        .iter()
+        .map(|column| column.replace(['\t', '\n', '\r'], " "))
+        .collect::<Vec<String>>()
+        .join("\t")
+}
 ///lookup the tree the mouse is hovering over
 pub fn overview_lookup(
     num_flamegraphs: usize,
@@ -30,6 +30,10 @@ fn main() {
 
     // Redirect tracing to console.log and friends:
     tracing_wasm::set_as_global_default();
+    //the mesh worker loads this module as well, it only answers jobs
+    if web_sys::window().is_none() {
+        return;
+    }
     let web_options = eframe::WebOptions::default();
     wasm_bindgen_futures::spawn_local(async {
         eframe::start_web(